@@ -0,0 +1,314 @@
+use bitcoin::{
+    blockdata::locktime::absolute::LockTime as AbsoluteLockTime,
+    blockdata::transaction::Version,
+    ScriptBuf,
+    Transaction,
+    Witness,
+};
+
+use crate::generator::{
+    random_range,
+    random_tx,
+    GenerationParams,
+};
+
+use rand::RngCore;
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Input and output counts, split where their CompactSize encoding changes width
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountClass {
+    Zero,
+    One,
+    OneByte,
+    ThreeBytes,
+}
+
+impl CountClass {
+    fn of(count: usize) -> Self {
+        match count {
+            0 => CountClass::Zero,
+            1 => CountClass::One,
+            2..=252 => CountClass::OneByte,
+            _ => CountClass::ThreeBytes,
+        }
+    }
+
+    fn range(&self) -> RangeInclusive<usize> {
+        match self {
+            CountClass::Zero => 0..=0,
+            CountClass::One => 1..=1,
+            CountClass::OneByte => 2..=252,
+            CountClass::ThreeBytes => 253..=usize::MAX,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionClass {
+    One,
+    Two,
+    OtherPositive,
+    ZeroOrNegative,
+}
+
+impl VersionClass {
+    fn of(version: i32) -> Self {
+        match version {
+            1 => VersionClass::One,
+            2 => VersionClass::Two,
+            3.. => VersionClass::OtherPositive,
+            _ => VersionClass::ZeroOrNegative,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockTimeClass {
+    Zero,
+    Height,
+    Time,
+}
+
+impl LockTimeClass {
+    fn of(lock_time: u32) -> Self {
+        match lock_time {
+            0 => LockTimeClass::Zero,
+            1..=499_999_999 => LockTimeClass::Height,
+            _ => LockTimeClass::Time,
+        }
+    }
+}
+
+/// One class of one feature of the transaction, the corpus should hold K vectors of each
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bucket {
+    Inputs(CountClass),
+    Outputs(CountClass),
+    Witness(bool),
+    ScriptSigs(bool),
+    Version(VersionClass),
+    LockTime(LockTimeClass),
+}
+
+impl Bucket {
+    pub const ALL: [Bucket; 18] = [
+        Bucket::Inputs(CountClass::One),
+        Bucket::Inputs(CountClass::OneByte),
+        Bucket::Inputs(CountClass::ThreeBytes),
+        Bucket::Outputs(CountClass::Zero),
+        Bucket::Outputs(CountClass::One),
+        Bucket::Outputs(CountClass::OneByte),
+        Bucket::Outputs(CountClass::ThreeBytes),
+        Bucket::Witness(false),
+        Bucket::Witness(true),
+        Bucket::ScriptSigs(false),
+        Bucket::ScriptSigs(true),
+        Bucket::Version(VersionClass::One),
+        Bucket::Version(VersionClass::Two),
+        Bucket::Version(VersionClass::OtherPositive),
+        Bucket::Version(VersionClass::ZeroOrNegative),
+        Bucket::LockTime(LockTimeClass::Zero),
+        Bucket::LockTime(LockTimeClass::Height),
+        Bucket::LockTime(LockTimeClass::Time),
+    ];
+
+    /// The buckets `tx` falls into, one per feature
+    pub fn of(tx: &Transaction) -> [Bucket; 6] {
+        [
+            Bucket::Inputs(CountClass::of(tx.input.len())),
+            Bucket::Outputs(CountClass::of(tx.output.len())),
+            Bucket::Witness(tx.input.iter().any(|input| !input.witness.is_empty())),
+            Bucket::ScriptSigs(tx.input.iter().any(|input| !input.script_sig.is_empty())),
+            Bucket::Version(VersionClass::of(tx.version.0)),
+            Bucket::LockTime(LockTimeClass::of(tx.lock_time.to_consensus_u32())),
+        ]
+    }
+
+    /// Only counts are limited by the configured ranges, every other class can always be produced
+    pub fn reachable(&self, params: &GenerationParams) -> bool {
+        match self {
+            Bucket::Inputs(class) => intersect(&params.input_count, &class.range()).is_some(),
+            Bucket::Outputs(class) => intersect(&params.output_count, &class.range()).is_some(),
+            Bucket::Witness(true) => *params.witness_length.end() > 0,
+            Bucket::ScriptSigs(true) => *params.script_sig_length.end() > 0,
+            _ => true,
+        }
+    }
+
+    /// Generate a random transaction that falls into this bucket
+    fn steered_tx<R: RngCore>(&self, rand: &mut R, params: &GenerationParams) -> Transaction {
+        let mut params = params.clone();
+
+        match self {
+            Bucket::Inputs(class) => {
+                params.input_count = intersect(&params.input_count, &class.range()).expect("reachable");
+            }
+            Bucket::Outputs(class) => {
+                params.output_count = intersect(&params.output_count, &class.range()).expect("reachable");
+            }
+            _ => {}
+        }
+
+        let mut tx = random_tx(rand, &params);
+
+        match self {
+            Bucket::Inputs(_) | Bucket::Outputs(_) => {}
+            Bucket::Witness(false) => {
+                for input in tx.input.iter_mut() {
+                    input.witness = Witness::new();
+                }
+            }
+            Bucket::Witness(true) => {
+                if tx.input.iter().all(|input| input.witness.is_empty()) {
+                    let mut item = vec![0u8; random_range(rand, &params.witness_item_length)];
+                    rand.fill_bytes(item.as_mut());
+
+                    tx.input[0].witness.push(item);
+                }
+            }
+            Bucket::ScriptSigs(false) => {
+                for input in tx.input.iter_mut() {
+                    input.script_sig = ScriptBuf::new();
+                }
+            }
+            Bucket::ScriptSigs(true) => {
+                if tx.input.iter().all(|input| input.script_sig.is_empty()) {
+                    let mut script_sig = vec![0u8; random_range(rand, &(1..=*params.script_sig_length.end()))];
+                    rand.fill_bytes(script_sig.as_mut());
+
+                    tx.input[0].script_sig = ScriptBuf::from_bytes(script_sig);
+                }
+            }
+            Bucket::Version(class) => {
+                let version = match class {
+                    VersionClass::One => 1,
+                    VersionClass::Two => 2,
+                    VersionClass::OtherPositive => 3 + (rand.next_u32() % (i32::MAX as u32 - 2)) as i32,
+                    VersionClass::ZeroOrNegative => -((rand.next_u32() % (i32::MAX as u32 + 1)) as i64) as i32,
+                };
+
+                tx.version = Version::non_standard(version);
+            }
+            Bucket::LockTime(class) => {
+                let lock_time = match class {
+                    LockTimeClass::Zero => 0,
+                    LockTimeClass::Height => 1 + rand.next_u32() % 499_999_999,
+                    LockTimeClass::Time => 500_000_000 + rand.next_u32() % (u32::MAX - 499_999_999),
+                };
+
+                tx.lock_time = AbsoluteLockTime::from_consensus(lock_time);
+            }
+        }
+
+        tx
+    }
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |class: &CountClass| match class {
+            CountClass::Zero => "0",
+            CountClass::One => "1",
+            CountClass::OneByte => "2-252",
+            CountClass::ThreeBytes => "253+",
+        };
+
+        match self {
+            Bucket::Inputs(class) => write!(f, "inputs {}", count(class)),
+            Bucket::Outputs(class) => write!(f, "outputs {}", count(class)),
+            Bucket::Witness(witness) => write!(f, "witness {}", if *witness { "yes" } else { "no" }),
+            Bucket::ScriptSigs(script_sigs) => write!(f, "scriptSigs {}", if *script_sigs { "yes" } else { "no" }),
+            Bucket::Version(class) => write!(f, "version {}", match class {
+                VersionClass::One => "1",
+                VersionClass::Two => "2",
+                VersionClass::OtherPositive => "3+",
+                VersionClass::ZeroOrNegative => "<=0",
+            }),
+            Bucket::LockTime(class) => write!(f, "locktime {}", match class {
+                LockTimeClass::Zero => "0",
+                LockTimeClass::Height => "height",
+                LockTimeClass::Time => "time",
+            }),
+        }
+    }
+}
+
+fn intersect(a: &RangeInclusive<usize>, b: &RangeInclusive<usize>) -> Option<RangeInclusive<usize>> {
+    let start = *a.start().max(b.start());
+    let end = *a.end().min(b.end());
+
+    (start <= end).then_some(start..=end)
+}
+
+/// Generate a transaction steered into a bucket chosen by `rand`
+///
+/// Entirely determined by `rand` and `params`, so vectors generated this way can be regenerated
+/// from their seed like any other.
+pub fn coverage_tx<R: RngCore>(rand: &mut R, params: &GenerationParams) -> Transaction {
+    let reachable: Vec<Bucket> = Bucket::ALL.into_iter()
+        .filter(|bucket| bucket.reachable(params))
+        .collect();
+
+    let target = reachable[rand.next_u64() as usize % reachable.len()];
+
+    target.steered_tx(rand, params)
+}
+
+/// Number of vectors seen in each bucket
+pub struct Coverage {
+    params: GenerationParams,
+    counts: [usize; Bucket::ALL.len()],
+}
+
+impl Coverage {
+    pub fn new(params: &GenerationParams) -> Self {
+        Coverage {
+            params: params.clone(),
+            counts: [0; Bucket::ALL.len()],
+        }
+    }
+
+    fn index(bucket: &Bucket) -> usize {
+        Bucket::ALL.iter().position(|b| b == bucket).expect("every bucket is in ALL")
+    }
+
+    /// Whether `tx` lands in any reachable bucket holding fewer than `minimum` vectors
+    pub fn is_needed(&self, tx: &Transaction, minimum: usize) -> bool {
+        Bucket::of(tx).iter()
+            .any(|bucket| self.counts[Self::index(bucket)] < minimum)
+    }
+
+    pub fn add(&mut self, tx: &Transaction) {
+        for bucket in Bucket::of(tx).iter() {
+            self.counts[Self::index(bucket)] += 1;
+        }
+    }
+
+    /// Whether every reachable bucket holds at least `minimum` vectors
+    pub fn is_complete(&self, minimum: usize) -> bool {
+        Bucket::ALL.iter()
+            .zip(self.counts.iter())
+            .all(|(bucket, count)| !bucket.reachable(&self.params) || *count >= minimum)
+    }
+
+    pub fn write_table<W: std::io::Write>(&self, out: &mut W, minimum: usize) -> std::io::Result<()> {
+        writeln!(out, "{:<20} {:>8}", "bucket", "vectors")?;
+
+        for (bucket, count) in Bucket::ALL.iter().zip(self.counts.iter()) {
+            let status = if !bucket.reachable(&self.params) {
+                "unreachable with these ranges"
+            } else if *count < minimum {
+                "UNDER"
+            } else {
+                ""
+            };
+
+            writeln!(out, "{:<20} {:>8} {}", bucket.to_string(), count, status)?;
+        }
+
+        Ok(())
+    }
+}
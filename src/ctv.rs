@@ -0,0 +1,247 @@
+use bitcoin::{
+    hashes::{
+        sha256,
+        Hash,
+        HashEngine,
+    },
+    hex::{
+        DisplayHex,
+        FromHex,
+    },
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::raw::{
+    write_bytes,
+    RawTransaction,
+};
+
+/// Hash of the concatenated serialized scriptSigs, or `None` if every scriptSig is empty
+///
+/// BIP-119 only commits to the scriptSigs when at least one of them is non-empty.
+fn script_sigs_hash(tx: &RawTransaction) -> Option<sha256::Hash> {
+    if tx.input.iter().all(|input| input.script_sig.is_empty()) {
+        return None;
+    }
+
+    let mut serialized = Vec::new();
+
+    for input in tx.input.iter() {
+        write_bytes(&mut serialized, &input.script_sig);
+    }
+
+    Some(sha256::Hash::hash(&serialized))
+}
+
+/// Hash of the concatenated input sequences
+fn sequences_hash(tx: &RawTransaction) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+
+    for input in tx.input.iter() {
+        engine.input(&input.sequence.to_le_bytes());
+    }
+
+    sha256::Hash::from_engine(engine)
+}
+
+/// Hash of the concatenated serialized outputs
+fn outputs_hash(tx: &RawTransaction) -> sha256::Hash {
+    let mut serialized = Vec::new();
+
+    for output in tx.output.iter() {
+        output.serialize_into(&mut serialized);
+    }
+
+    sha256::Hash::hash(&serialized)
+}
+
+/// Everything `DefaultCheckTemplateVerifyHash` commits to besides the version, lock time and
+/// input index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TemplateComponents {
+    pub script_sigs_hash: Option<sha256::Hash>,
+    pub input_count: u32,
+    pub sequences_hash: sha256::Hash,
+    pub output_count: u32,
+    pub outputs_hash: sha256::Hash,
+}
+
+impl TemplateComponents {
+    pub fn of(tx: &RawTransaction) -> Self {
+        TemplateComponents {
+            script_sigs_hash: script_sigs_hash(tx),
+            input_count: tx.input.len() as u32,
+            sequences_hash: sequences_hash(tx),
+            output_count: tx.output.len() as u32,
+            outputs_hash: outputs_hash(tx),
+        }
+    }
+}
+
+/// One field of the template hash preimage
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreimageField {
+    pub name: &'static str,
+    /// Serialized bytes, `None` for a field the transaction doesn't commit to
+    pub bytes: Option<Vec<u8>>,
+    /// What the bytes stand for
+    pub value: String,
+}
+
+/// The fields of the preimage of `tx` spent at `input_index`, in order
+pub fn preimage_fields(tx: &RawTransaction, input_index: u32) -> Vec<PreimageField> {
+    let components = TemplateComponents::of(tx);
+
+    let field = |name, bytes: &[u8], value: String| PreimageField {
+        name,
+        bytes: Some(bytes.to_vec()),
+        value,
+    };
+
+    let script_sig_count = tx.input.iter().filter(|input| !input.script_sig.is_empty()).count();
+
+    vec![
+        field("nVersion", &tx.version.to_le_bytes(), tx.version.to_string()),
+        field("nLockTime", &tx.lock_time.to_le_bytes(), tx.lock_time.to_string()),
+        PreimageField {
+            name: "scriptSigs hash",
+            bytes: components.script_sigs_hash.map(|hash| hash.to_byte_array().to_vec()),
+            value: match components.script_sigs_hash {
+                Some(_) => format!("sha256 of all scriptSigs, {} of them non-empty", script_sig_count),
+                None => "absent, every scriptSig is empty".to_string(),
+            },
+        },
+        field("input count", &components.input_count.to_le_bytes(), components.input_count.to_string()),
+        field("sequences hash", components.sequences_hash.as_ref(), format!("sha256 of {} sequences", tx.input.len())),
+        field("output count", &components.output_count.to_le_bytes(), components.output_count.to_string()),
+        field("outputs hash", components.outputs_hash.as_ref(), format!("sha256 of {} outputs", tx.output.len())),
+        field("input index", &input_index.to_le_bytes(), input_index.to_string()),
+    ]
+}
+
+/// The bytes hashed by `default_template_hash`, in order
+pub fn preimage(tx: &RawTransaction, input_index: u32) -> Vec<u8> {
+    preimage_fields(tx, input_index).into_iter()
+        .flat_map(|field| field.bytes.unwrap_or_default())
+        .collect()
+}
+
+/// Compute the BIP-119 `DefaultCheckTemplateVerifyHash` of `tx` spent at `input_index`
+///
+/// The result is the raw SHA256 output, in the byte order it would be pushed in a script.
+pub fn default_template_hash(tx: &RawTransaction, input_index: u32) -> sha256::Hash {
+    sha256::Hash::hash(&preimage(tx, input_index))
+}
+
+/// Order of the bytes of a hex encoded template hash
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteOrder {
+    /// The raw SHA256 output, as pushed in a CTV script
+    Internal,
+    /// Reversed, as `uint256::GetHex()` prints it and `getdefaulttemplate` returns it
+    #[default]
+    Display,
+}
+
+/// Convert a hex encoded hash from one byte order to the other
+pub fn reverse_hex(hex: &str) -> Result<String, String> {
+    let mut bytes = Vec::<u8>::from_hex(hex)
+        .map_err(|e| format!("invalid hex {:?}: {}", hex, e))?;
+    bytes.reverse();
+
+    Ok(bytes.to_lower_hex_string())
+}
+
+/// Hex encode a template hash the way Bitcoin Core's `uint256::GetHex()` does (byte reversed)
+///
+/// This is the encoding `getdefaulttemplate` returns, so it is what ends up in `result`.
+pub fn template_hash_hex(hash: &sha256::Hash) -> String {
+    let mut bytes = hash.to_byte_array();
+    bytes.reverse();
+
+    bytes.to_lower_hex_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Template hash of `hex_tx` at `index`, in internal and display byte order
+    fn check(hex_tx: &str, index: u32, internal: &str, display: &str) {
+        let tx = RawTransaction::deserialize_hex(hex_tx).expect("test transaction");
+        let hash = default_template_hash(&tx, index);
+
+        assert_eq!(hash.to_byte_array().to_lower_hex_string(), internal, "internal order, index {}", index);
+        assert_eq!(template_hash_hex(&hash), display, "display order, index {}", index);
+    }
+
+    // Expected hashes computed independently from the BIP-119 preimage definition
+
+    /// Version 2, one input without a scriptSig, one output
+    const NO_SCRIPT_SIGS: &str = "0200000001aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0000000000ffffffff01e803000000000000015100000000";
+
+    /// `NO_SCRIPT_SIGS` with a witness, which isn't committed to
+    const WITNESS: &str = "02000000000101aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0000000000ffffffff01e8030000000000000151010301020300000000";
+
+    /// Version 1, time lock, first input without a scriptSig, second with one, two outputs
+    const MIXED_SCRIPT_SIGS: &str = "0100000002aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0100000000fdffffffbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb070000000251520100000002881300000000000016001400000000000000000000000000000000000000000000000000000000016a0065cd1d";
+
+    #[test]
+    fn no_script_sigs() {
+        check(NO_SCRIPT_SIGS, 0,
+            "57867d08f39639cb3225a77904b41350881a205a2c6c80e1823819aaf39e0520",
+            "20059ef3aa193882e1806c2c5a201a885013b40479a72532cb3996f3087d8657");
+    }
+
+    #[test]
+    fn witness_not_committed() {
+        check(WITNESS, 0,
+            "57867d08f39639cb3225a77904b41350881a205a2c6c80e1823819aaf39e0520",
+            "20059ef3aa193882e1806c2c5a201a885013b40479a72532cb3996f3087d8657");
+    }
+
+    #[test]
+    fn mixed_script_sigs() {
+        check(MIXED_SCRIPT_SIGS, 0,
+            "06eaefe0cfb897d7c3f0f5beb24fed58aa68203c50827f25fc8499f65a2cc4f3",
+            "f3c42c5af69984fc257f82503c2068aa58ed4fb2bef5f0c3d797b8cfe0efea06");
+        check(MIXED_SCRIPT_SIGS, 1,
+            "cd363c38510e5145039c2706268c0d56f23b1bbaa145bca4a7dcbf7024795b7e",
+            "7e5b792470bfdca7a4bc45a1ba1b3bf2560d8c2606279c0345510e51383c36cd");
+    }
+
+    #[test]
+    fn spend_index_past_input_count() {
+        check(MIXED_SCRIPT_SIGS, 2,
+            "263bc4cf874cf8753beb8a2626394fca9127d7ffdfccf05d8adf6aefc3a28ea5",
+            "a58ea2c3ef6adf8a5df0ccdfffd72791ca4f3926268aeb3b75f84c87cfc43b26");
+        check(MIXED_SCRIPT_SIGS, u32::MAX,
+            "7b7ace56645efb3e7c1581c8f5258eaf89b1099059db8350ca2f48a3347e069e",
+            "9e067e34a3482fca5083db599009b189af8e25f5c881157c3efb5e6456ce7a7b");
+    }
+
+    /// Every vector of upstream `bip-0119/vectors/ctvhash.json`, run with
+    /// `CTVHASH_JSON=<path> cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs CTVHASH_JSON"]
+    fn upstream_vectors() {
+        let path = std::env::var_os("CTVHASH_JSON").expect("CTVHASH_JSON points at upstream ctvhash.json");
+
+        let vectors = crate::vectors::load_vectors_file(path).expect("load upstream vectors");
+        assert!(!vectors.is_empty());
+
+        for vector in vectors {
+            let tx = RawTransaction::deserialize_hex(&vector.transaction).expect("upstream transaction");
+
+            for (index, expected) in vector.spend_index.iter().zip(vector.result.iter()) {
+                assert_eq!(&template_hash_hex(&default_template_hash(&tx, *index)), expected,
+                    "spend_index {} of {}", index, vector.transaction);
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::oracle::Oracle;
+use crate::raw::RawTransaction;
+use crate::seed::Seed;
+use crate::shrink::shrink;
+
+/// What a single oracle made of a transaction
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Answer {
+    Result(String),
+    Error(String),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OracleAnswer {
+    pub oracle: String,
+    #[serde(flatten)]
+    pub answer: Answer,
+}
+
+/// A transaction and input the oracles don't all agree on
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Disagreement {
+    #[serde(rename = "hex_tx")]
+    pub transaction: String,
+    pub spend_index: u32,
+    /// Per-vector seed of the generated transaction, if it came from one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<Seed>,
+    /// Whether the transaction was shrunk from the one `seed` generates
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shrunk: bool,
+    pub answers: Vec<OracleAnswer>,
+}
+
+/// Ask every oracle for the template hash of `tx` spent at `index`
+pub fn answers(oracles: &[Box<dyn Oracle>], tx: &RawTransaction, index: u32) -> Vec<OracleAnswer> {
+    oracles.iter()
+        .map(|oracle| OracleAnswer {
+            oracle: oracle.name(),
+            answer: match oracle.template_hash(tx, index) {
+                Ok(hash) => Answer::Result(hash.to_lowercase()),
+                Err(e) => Answer::Error(e),
+            },
+        })
+        .collect()
+}
+
+/// Whether every oracle returned the same hash, an error from any of them counts as disagreement
+pub fn agree(answers: &[OracleAnswer]) -> bool {
+    answers.iter().all(|answer| {
+        matches!(answer.answer, Answer::Result(_)) && answer.answer == answers[0].answer
+    })
+}
+
+/// Which oracles errored and which gave the same hash as each other
+///
+/// Each oracle maps to `None` for an error, or the position of the first oracle with the same hash.
+fn signature(answers: &[OracleAnswer]) -> Vec<Option<usize>> {
+    answers.iter()
+        .map(|answer| match &answer.answer {
+            Answer::Result(_) => answers.iter().position(|other| other.answer == answer.answer),
+            Answer::Error(_) => None,
+        })
+        .collect()
+}
+
+/// Compare every oracle on `tx` spent at `index`, returning the disagreement if there is one
+pub fn compare(oracles: &[Box<dyn Oracle>], tx: &RawTransaction, index: u32) -> Option<Disagreement> {
+    let answers = answers(oracles, tx, index);
+
+    (!agree(&answers)).then(|| Disagreement {
+        transaction: tx.serialize_hex(),
+        spend_index: index,
+        seed: None,
+        shrunk: false,
+        answers,
+    })
+}
+
+/// Shrink `tx` spent at `index` to a minimal transaction and index the oracles still disagree on
+///
+/// Only simplifications with the same `signature` are kept, so the same oracles error and the rest
+/// split into the same groups of hashes. Otherwise an oracle refusing some smaller transaction
+/// could stand in for the disagreement being shrunk.
+pub fn shrink_disagreement(oracles: &[Box<dyn Oracle>], tx: RawTransaction, index: u32) -> Disagreement {
+    let original = signature(&answers(oracles, &tx, index));
+
+    let (tx, index) = shrink(tx, index, |tx, index| signature(&answers(oracles, tx, index)) == original);
+
+    Disagreement {
+        transaction: tx.serialize_hex(),
+        spend_index: index,
+        seed: None,
+        shrunk: true,
+        answers: answers(oracles, &tx, index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::raw::{
+        RawTxIn,
+        RawTxOut,
+    };
+
+    /// An oracle answering with a function of the transaction and index
+    struct FakeOracle(&'static str, fn(&RawTransaction, u32) -> Result<String, String>);
+
+    impl Oracle for FakeOracle {
+        fn name(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn template_hash(&self, tx: &RawTransaction, index: u32) -> Result<String, String> {
+            (self.1)(tx, index)
+        }
+    }
+
+    fn correct(tx: &RawTransaction, index: u32) -> Result<String, String> {
+        Ok(format!("{}:{}", tx.serialize_hex(), index))
+    }
+
+    /// Wrong with more than one scriptSig, and refuses transactions without outputs
+    fn buggy(tx: &RawTransaction, index: u32) -> Result<String, String> {
+        if tx.output.is_empty() {
+            return Err("no outputs unsupported".to_string());
+        }
+
+        if tx.input.iter().filter(|input| !input.script_sig.is_empty()).count() > 1 {
+            return Ok("wrong".to_string());
+        }
+
+        correct(tx, index)
+    }
+
+    fn answer(oracle: &str, answer: Answer) -> OracleAnswer {
+        OracleAnswer {
+            oracle: oracle.to_string(),
+            answer,
+        }
+    }
+
+    #[test]
+    fn signature_groups_hashes() {
+        let answers = [
+            answer("a", Answer::Result("x".to_string())),
+            answer("b", Answer::Error("e".to_string())),
+            answer("c", Answer::Result("y".to_string())),
+            answer("d", Answer::Result("x".to_string())),
+        ];
+
+        assert_eq!(signature(&answers), vec![Some(0), None, Some(2), Some(0)]);
+    }
+
+    #[test]
+    fn shrinking_keeps_the_original_failure() {
+        let oracles: Vec<Box<dyn Oracle>> = vec![
+            Box::new(FakeOracle("correct", correct)),
+            Box::new(FakeOracle("buggy", buggy)),
+        ];
+
+        let input = |script_sig: Vec<u8>| RawTxIn {
+            txid: [7; 32],
+            vout: 1,
+            script_sig,
+            sequence: 0xffff_ffff,
+            witness: vec![vec![1, 2, 3]],
+        };
+
+        let tx = RawTransaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![input(vec![0x51; 10]), input(Vec::new()), input(vec![0x52; 10])],
+            output: vec![
+                RawTxOut { value: 1_000, script_pubkey: vec![0x51; 20] },
+                RawTxOut { value: 2_000, script_pubkey: vec![0x52; 20] },
+            ],
+        };
+
+        let disagreement = shrink_disagreement(&oracles, tx, 2);
+        let shrunk = RawTransaction::deserialize_hex(&disagreement.transaction).expect("shrunk transaction");
+
+        assert_eq!(disagreement.answers[1].answer, Answer::Result("wrong".to_string()));
+        assert_eq!(shrunk.input.len(), 2);
+        assert_eq!(shrunk.output.len(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+use bitcoin::{
+    blockdata::locktime::absolute::LockTime as AbsoluteLockTime,
+    blockdata::transaction::Version,
+    hashes::Hash,
+    Amount,
+    OutPoint,
+    ScriptBuf,
+    Sequence,
+    Transaction,
+    Txid,
+    TxIn,
+    TxOut,
+    Witness,
+};
+
+use crate::raw::RawTransaction;
+
+/// Tag of vectors with at least one output value above `MAX_MONEY`
+///
+/// They're still consensus-encodable and rust-bitcoin decodes them, but `Amount` arithmetic and
+/// anything that checks values against `MAX_MONEY` will refuse them.
+pub const AMOUNT_ABOVE_MAX_MONEY: &str = "amount-above-max-money";
+
+/// Counts on either side of each CompactSize width change
+const COUNT_BOUNDARIES: [usize; 4] = [252, 253, 65_535, 65_536];
+
+/// Script lengths on either side of push opcode, CompactSize, push size and script size limits
+const SCRIPT_LENGTH_BOUNDARIES: [usize; 12] = [
+    75, 76, // OP_PUSHDATA1
+    252, 253, // CompactSize width
+    255, 256, // OP_PUSHDATA2
+    520, 521, // MAX_SCRIPT_ELEMENT_SIZE
+    10_000, 10_001, // MAX_SCRIPT_SIZE
+    65_535, 65_536, // OP_PUSHDATA4, CompactSize width
+];
+
+/// Witness item lengths on either side of the CompactSize width change and the standard push limit
+const WITNESS_ITEM_LENGTH_BOUNDARIES: [usize; 4] = [252, 253, 520, 521];
+
+/// A hand-built transaction and a description of what it exercises
+pub struct EdgeCase<T = Transaction> {
+    pub tags: Vec<String>,
+    pub tx: T,
+}
+
+/// Deterministic, recognizable bytes
+fn filler(length: usize) -> Vec<u8> {
+    (0..length).map(|i| (i % 251) as u8).collect()
+}
+
+fn edge_input(index: usize) -> TxIn {
+    TxIn {
+        previous_output: OutPoint {
+            txid: Txid::hash(&(index as u64).to_le_bytes()),
+            vout: index as u32,
+        },
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::MAX,
+        witness: Witness::new(),
+    }
+}
+
+fn edge_output() -> TxOut {
+    TxOut {
+        value: Amount::from_sat(1),
+        script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+    }
+}
+
+/// One input and one output, everything else is a variation on this
+fn base_tx() -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: AbsoluteLockTime::ZERO,
+        input: vec![edge_input(0)],
+        output: vec![edge_output()],
+    }
+}
+
+fn edge_case(tag: String, tx: Transaction) -> EdgeCase {
+    EdgeCase {
+        tags: vec![tag],
+        tx,
+    }
+}
+
+/// Transactions sitting on serialization boundaries that random generation almost never hits
+pub fn edge_cases() -> Vec<EdgeCase> {
+    let mut cases = Vec::new();
+
+    let mut tx = base_tx();
+    tx.output.clear();
+    cases.push(edge_case("outputs=0".to_string(), tx));
+
+    for count in COUNT_BOUNDARIES {
+        let mut tx = base_tx();
+        tx.input = (0..count).map(edge_input).collect();
+        cases.push(edge_case(format!("inputs={}", count), tx));
+
+        let mut tx = base_tx();
+        tx.output = (0..count).map(|_| edge_output()).collect();
+        cases.push(edge_case(format!("outputs={}", count), tx));
+    }
+
+    for length in SCRIPT_LENGTH_BOUNDARIES {
+        let mut tx = base_tx();
+        tx.input[0].script_sig = ScriptBuf::from_bytes(filler(length));
+        cases.push(edge_case(format!("script_sig_length={}", length), tx));
+
+        let mut tx = base_tx();
+        tx.output[0].script_pubkey = ScriptBuf::from_bytes(filler(length));
+        cases.push(edge_case(format!("script_pubkey_length={}", length), tx));
+    }
+
+    for count in &COUNT_BOUNDARIES[..2] {
+        let mut tx = base_tx();
+        tx.input[0].witness = Witness::from_slice(&vec![vec![0x01]; *count]);
+        cases.push(edge_case(format!("witness_length={}", count), tx));
+    }
+
+    for length in WITNESS_ITEM_LENGTH_BOUNDARIES {
+        let mut tx = base_tx();
+        tx.input[0].witness.push(filler(length));
+        cases.push(edge_case(format!("witness_item_length={}", length), tx));
+    }
+
+    cases
+}
+
+/// Outputs on either side of the amount limits rust-bitcoin and Bitcoin Core enforce
+///
+/// Every individual amount is at most `MAX_MONEY` so rust-bitcoin can parse them, but some of the
+/// sums exceed it, which consensus rejects but the template hash doesn't care about.
+pub fn amount_edge_cases() -> Vec<EdgeCase> {
+    let max_money = Amount::MAX_MONEY.to_sat();
+
+    let mut cases = Vec::new();
+
+    for (name, value) in [
+        ("0", 0),
+        ("1", 1),
+        ("max_money-1", max_money - 1),
+        ("max_money", max_money),
+    ] {
+        let mut tx = base_tx();
+        tx.output[0].value = Amount::from_sat(value);
+        cases.push(edge_case(format!("amount={}", name), tx));
+    }
+
+    for (name, values) in [
+        ("output_sum=max_money", vec![max_money - 1, 1]),
+        ("output_sum=max_money+1", vec![max_money, 1]),
+        ("output_sum=2*max_money", vec![max_money, max_money]),
+        // Overflows a signed 64 bit accumulator
+        ("output_sum>i64::MAX", vec![max_money; 4_612]),
+    ] {
+        let mut tx = base_tx();
+        tx.output = values.into_iter()
+            .map(|value| TxOut {
+                value: Amount::from_sat(value),
+                ..edge_output()
+            })
+            .collect();
+        cases.push(edge_case(name.to_string(), tx));
+    }
+
+    cases
+}
+
+/// Single outputs above `MAX_MONEY`, which only a `RawTransaction` can carry
+pub fn raw_amount_edge_cases() -> Vec<EdgeCase<RawTransaction>> {
+    let max_money = Amount::MAX_MONEY.to_sat();
+
+    [
+        ("max_money+1", max_money + 1),
+        ("i64::MAX", i64::MAX as u64),
+        ("i64::MAX+1", i64::MAX as u64 + 1),
+        ("u64::MAX", u64::MAX),
+    ]
+        .into_iter()
+        .map(|(name, value)| {
+            let mut tx = RawTransaction::from(&base_tx());
+            tx.output[0].value = value;
+
+            EdgeCase {
+                tags: vec![AMOUNT_ABOVE_MAX_MONEY.to_string(), format!("amount={}", name)],
+                tx,
+            }
+        })
+        .collect()
+}
+
+/// Spend indices around the ends of the input list, plus the largest possible index
+pub fn edge_spend_indices(input_count: usize) -> Vec<u32> {
+    let input_count = input_count as u32;
+
+    let mut spend_index = vec![0, input_count - 1, input_count, u32::MAX];
+    spend_index.dedup();
+
+    spend_index
+}
@@ -0,0 +1,766 @@
+use bitcoin::{
+    blockdata::locktime::absolute::LockTime as AbsoluteLockTime,
+    Amount,
+    hashes::Hash,
+    OutPoint,
+    ScriptBuf,
+    Sequence,
+    Transaction,
+    Txid,
+    TxIn,
+    TxOut,
+    blockdata::transaction::Version,
+    Witness,
+};
+
+use clap::ValueEnum;
+
+use crate::raw::RawTransaction;
+
+use rand::RngCore;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use std::cmp::max;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Generate a random integer in a given range
+pub fn random_range<R: RngCore>(rand: &mut R, range: &RangeInclusive<usize>) -> usize {
+    let x = rand.next_u64() as usize;
+    let size = max(range.end() - range.start(), 0) + 1;
+
+    range.start() + (x % size)
+}
+
+const INPUT_COUNT: RangeInclusive<usize> = 1..=129;
+const OUTPUT_COUNT: RangeInclusive<usize> = 0..=129;
+
+const SCRIPT_PUBKEY_LENGTH: RangeInclusive<usize> = 0..=129;
+const SCRIPT_SIG_LENGTH: RangeInclusive<usize> = 0..=129;
+const WITNESS_LENGTH: RangeInclusive<usize> = 0..=129;
+const WITNESS_ITEM_LENGTH: RangeInclusive<usize> = 0..=520;
+
+/// An approximate amount of random bytes in the transaction
+/// Note that this doesn't account for things like VarInt lengths
+const RANDOM_BYTES_COUNT: RangeInclusive<usize> = 0..=10_000;
+
+/// Longest scriptPubKey `fit_weight` pads with, the consensus limit on executed scripts
+const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Parse a range written as `MIN..=MAX` or a single value `N`
+pub fn parse_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let parse_bound = |bound: &str| bound.trim().parse::<usize>()
+        .map_err(|e| format!("invalid range bound {:?}: {}", bound, e));
+
+    let range = match s.split_once("..=") {
+        Some((start, end)) => parse_bound(start)?..=parse_bound(end)?,
+        None => {
+            let n = parse_bound(s)?;
+            n..=n
+        }
+    };
+
+    if range.start() > range.end() {
+        return Err(format!("empty range {}", s));
+    }
+
+    Ok(range)
+}
+
+/// (De)serialize ranges in the same `MIN..=MAX` notation accepted on the command line
+mod range_format {
+    use serde::{
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+
+    use std::ops::RangeInclusive;
+
+    pub fn serialize<S: Serializer>(range: &RangeInclusive<usize>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{}..={}", range.start(), range.end()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RangeInclusive<usize>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+
+        super::parse_range(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// `range_format` for ranges that may be left out
+mod optional_range_format {
+    use serde::{
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+
+    use std::ops::RangeInclusive;
+
+    pub fn serialize<S: Serializer>(range: &Option<RangeInclusive<usize>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match range {
+            Some(range) => super::range_format::serialize(range, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<RangeInclusive<usize>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| super::parse_range(&s).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// How individual fields are filled in, independent of their counts and lengths
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldStyle {
+    /// Uniformly random values and bytes
+    #[default]
+    Random,
+    /// Mainnet-like versions, sequences, amounts and standard script templates
+    ///
+    /// Script and witness lengths come from the templates rather than the configured ranges.
+    Realistic,
+    /// Values sitting on consensus and serialization boundaries
+    Adversarial,
+}
+
+/// Named presets for `GenerationParams`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// The built-in ranges with uniformly random fields
+    #[default]
+    Default,
+    /// One or two small inputs and outputs
+    Minimal,
+    /// Mainnet-like script types and sizes
+    Realistic,
+    /// Boundary counts, huge scripts and odd versions
+    Adversarial,
+    /// Transactions near the maximum standard weight
+    Stress,
+}
+
+impl Profile {
+    pub fn params(&self) -> GenerationParams {
+        match self {
+            Profile::Default => GenerationParams::default(),
+            Profile::Minimal => GenerationParams {
+                input_count: 1..=2,
+                output_count: 1..=2,
+                script_pubkey_length: 0..=34,
+                script_sig_length: 0..=34,
+                witness_length: 0..=2,
+                witness_item_length: 0..=72,
+                random_bytes_count: 0..=500,
+                style: FieldStyle::Random,
+                weight: None,
+            },
+            Profile::Realistic => GenerationParams {
+                input_count: 1..=10,
+                output_count: 1..=5,
+                random_bytes_count: 0..=5_000,
+                style: FieldStyle::Realistic,
+                ..GenerationParams::default()
+            },
+            Profile::Adversarial => GenerationParams {
+                input_count: 1..=260,
+                output_count: 0..=260,
+                script_pubkey_length: 0..=10_000,
+                script_sig_length: 0..=10_000,
+                witness_length: 0..=260,
+                witness_item_length: 0..=10_000,
+                random_bytes_count: 0..=100_000,
+                style: FieldStyle::Adversarial,
+                weight: None,
+            },
+            // Just under MAX_STANDARD_TX_WEIGHT, 400,000 WU
+            Profile::Stress => GenerationParams {
+                input_count: 100..=500,
+                output_count: 100..=500,
+                random_bytes_count: 50_000..=90_000,
+                weight: Some(390_000..=399_000),
+                ..GenerationParams::default()
+            },
+        }
+    }
+}
+
+/// Ranges that shape the random transactions
+///
+/// Defaults to the compile-time constants above. A config file only needs to mention the ranges it
+/// overrides.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationParams {
+    #[serde(with = "range_format")]
+    pub input_count: RangeInclusive<usize>,
+
+    #[serde(with = "range_format")]
+    pub output_count: RangeInclusive<usize>,
+
+    #[serde(with = "range_format")]
+    pub script_pubkey_length: RangeInclusive<usize>,
+
+    #[serde(with = "range_format")]
+    pub script_sig_length: RangeInclusive<usize>,
+
+    #[serde(with = "range_format")]
+    pub witness_length: RangeInclusive<usize>,
+
+    #[serde(with = "range_format")]
+    pub witness_item_length: RangeInclusive<usize>,
+
+    #[serde(with = "range_format")]
+    pub random_bytes_count: RangeInclusive<usize>,
+
+    pub style: FieldStyle,
+
+    /// Weight in WU to trim or pad each transaction to, left as generated if absent
+    #[serde(with = "optional_range_format", skip_serializing_if = "Option::is_none")]
+    pub weight: Option<RangeInclusive<usize>>,
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        GenerationParams {
+            input_count: INPUT_COUNT,
+            output_count: OUTPUT_COUNT,
+            script_pubkey_length: SCRIPT_PUBKEY_LENGTH,
+            script_sig_length: SCRIPT_SIG_LENGTH,
+            witness_length: WITNESS_LENGTH,
+            witness_item_length: WITNESS_ITEM_LENGTH,
+            random_bytes_count: RANDOM_BYTES_COUNT,
+            style: FieldStyle::Random,
+            weight: None,
+        }
+    }
+}
+
+impl GenerationParams {
+    /// Layer a JSON config file over `self`, unmentioned ranges keep their current values
+    pub fn with_file(&self, path: &Path) -> Result<Self, String> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+
+        let overrides: serde_json::Map<String, serde_json::Value> = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| format!("can't parse {}: {}", path.display(), e))?;
+
+        let mut merged = match serde_json::to_value(self).expect("params serialize") {
+            serde_json::Value::Object(map) => map,
+            _ => unreachable!("params serialize to an object"),
+        };
+        merged.extend(overrides);
+
+        let params: Self = serde_json::from_value(serde_json::Value::Object(merged))
+            .map_err(|e| format!("can't parse {}: {}", path.display(), e))?;
+
+        params.validate()?;
+
+        Ok(params)
+    }
+
+    /// Reject ranges that would produce transactions we can't round-trip
+    pub fn validate(&self) -> Result<(), String> {
+        // A zero input transaction is ambiguous with the segwit marker and won't deserialize
+        if *self.input_count.start() < 1 {
+            return Err("input count must be at least 1".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Generate a random number of random bytes within `length`, no more than max_bytes unless the
+/// minimum length needs more
+fn random_bytes_lt<R: RngCore>(rand: &mut R, length: &RangeInclusive<usize>, max_bytes: &mut usize) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    let length = if *max_bytes < 1 {
+        *length.start()
+    } else {
+        // Hacky, but should reduce the likelihood of generating lots of zero-length outputs on
+        // transactions, without biasing the average transaction size too much, hopefully.
+        let range = (*max_bytes + length.end()).div_ceil(2);
+
+        max(random_range(rand, length) % (range + 1), *length.start())
+    };
+
+    *max_bytes = max_bytes.saturating_sub(length);
+
+    result.resize(length, 0);
+
+    rand.fill_bytes(result.as_mut());
+
+    result
+}
+
+fn random_witness_item<R: RngCore>(rand: &mut R, params: &GenerationParams, max_bytes: &mut usize) -> Vec<u8> {
+    random_bytes_lt(rand, &params.witness_item_length, max_bytes)
+}
+
+/// Pick one of `choices` uniformly
+fn random_choice<R: RngCore, T: Copy>(rand: &mut R, choices: &[T]) -> T {
+    choices[rand.next_u64() as usize % choices.len()]
+}
+
+/// Pick a count from `range`, favoring CompactSize width changes for adversarial transactions
+fn random_count<R: RngCore>(rand: &mut R, range: &RangeInclusive<usize>, style: FieldStyle) -> usize {
+    if style == FieldStyle::Adversarial && rand.next_u32().is_multiple_of(2) {
+        let boundaries: Vec<usize> = [*range.start(), *range.end(), 1, 252, 253]
+            .into_iter()
+            .filter(|count| range.contains(count))
+            .collect();
+
+        return random_choice(rand, &boundaries);
+    }
+
+    random_range(rand, range)
+}
+
+fn random_version<R: RngCore>(rand: &mut R, style: FieldStyle) -> Version {
+    match style {
+        FieldStyle::Random => Version::non_standard(rand.next_u32() as i32),
+        FieldStyle::Realistic => random_choice(rand, &[Version::ONE, Version::TWO, Version::TWO, Version::TWO]),
+        FieldStyle::Adversarial => {
+            let random = rand.next_u32() as i32;
+
+            Version::non_standard(random_choice(rand, &[0, 1, 2, 3, -1, i32::MAX, i32::MIN, random]))
+        }
+    }
+}
+
+fn random_lock_time<R: RngCore>(rand: &mut R, style: FieldStyle) -> AbsoluteLockTime {
+    let lock_time = match style {
+        FieldStyle::Random => rand.next_u32(),
+        // Usually disabled, otherwise anti-fee-sniping near the current tip
+        FieldStyle::Realistic => {
+            let height = 800_000 + rand.next_u32() % 200_000;
+
+            random_choice(rand, &[0, 0, 0, height])
+        }
+        FieldStyle::Adversarial => random_choice(rand, &[0, 1, 499_999_999, 500_000_000, u32::MAX]),
+    };
+
+    AbsoluteLockTime::from_consensus(lock_time)
+}
+
+fn random_sequence<R: RngCore>(rand: &mut R, style: FieldStyle) -> Sequence {
+    let sequence = match style {
+        FieldStyle::Random => rand.next_u32(),
+        FieldStyle::Realistic => random_choice(rand, &[0xffff_ffff, 0xffff_fffe, 0xffff_fffd]),
+        // Disable flag, type flag and relative locktime mask boundaries
+        FieldStyle::Adversarial => random_choice(rand, &[0, 1, 0x0000_ffff, 0x0040_0000, 0x7fff_ffff, 0x8000_0000, 0xffff_fffe, 0xffff_ffff]),
+    };
+
+    Sequence::from_consensus(sequence)
+}
+
+fn random_amount<R: RngCore>(rand: &mut R, style: FieldStyle) -> Amount {
+    let sats_modulus = Amount::MAX_MONEY.to_sat() + 1;
+
+    match style {
+        FieldStyle::Random => Amount::from_sat(rand.next_u64() % sats_modulus),
+        FieldStyle::Realistic => Amount::from_sat(546 + rand.next_u64() % 10_000_000_000),
+        FieldStyle::Adversarial => {
+            let random = rand.next_u64() % sats_modulus;
+
+            Amount::from_sat(random_choice(rand, &[0, 1, Amount::MAX_MONEY.to_sat(), random]))
+        }
+    }
+}
+
+/// Random bytes of exactly `length`, charged against `max_bytes`
+fn random_bytes_exact<R: RngCore>(rand: &mut R, length: usize, max_bytes: &mut usize) -> Vec<u8> {
+    let mut result = vec![0u8; length];
+    rand.fill_bytes(result.as_mut());
+
+    *max_bytes = max_bytes.saturating_sub(length);
+
+    result
+}
+
+/// A scriptPubKey following one of the common mainnet templates
+fn realistic_script_pubkey<R: RngCore>(rand: &mut R, max_bytes: &mut usize) -> ScriptBuf {
+    let (prefix, hash_length, suffix): (&[u8], usize, &[u8]) = match rand.next_u32() % 6 {
+        // P2PKH
+        0 => (&[0x76, 0xa9, 0x14], 20, &[0x88, 0xac]),
+        // P2SH
+        1 => (&[0xa9, 0x14], 20, &[0x87]),
+        // P2WPKH
+        2 => (&[0x00, 0x14], 20, &[]),
+        // P2WSH
+        3 => (&[0x00, 0x20], 32, &[]),
+        // P2TR
+        4 => (&[0x51, 0x20], 32, &[]),
+        // OP_RETURN
+        _ => (&[0x6a, 0x20], 32, &[]),
+    };
+
+    let mut script = prefix.to_vec();
+    script.extend(random_bytes_exact(rand, hash_length, max_bytes));
+    script.extend_from_slice(suffix);
+
+    ScriptBuf::from_bytes(script)
+}
+
+/// A DER-ish signature length followed by a compressed pubkey, as pushed by P2PKH and P2WPKH
+fn realistic_signature_and_pubkey<R: RngCore>(rand: &mut R, max_bytes: &mut usize) -> (Vec<u8>, Vec<u8>) {
+    let signature_length = 71 + (rand.next_u32() % 2) as usize;
+
+    let signature = random_bytes_exact(rand, signature_length, max_bytes);
+    let mut pubkey = random_bytes_exact(rand, 33, max_bytes);
+    pubkey[0] = 0x02 | (pubkey[0] & 1);
+
+    (signature, pubkey)
+}
+
+/// Which inputs of a transaction carry a witness, or a scriptSig
+///
+/// Mixed patterns are where a sloppy "any scriptSig is non-empty" check goes wrong, so they're as
+/// likely as the uniform ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InputPattern {
+    Never,
+    Always,
+    /// Each input independently
+    Mixed,
+    OnlyFirst,
+    OnlyLast,
+    /// Every input except the one at this index
+    AllButOne(usize),
+}
+
+impl InputPattern {
+    fn random<R: RngCore>(rand: &mut R, input_count: usize) -> Self {
+        match rand.next_u32() % 7 {
+            0 | 1 => InputPattern::Never,
+            2 => InputPattern::Always,
+            3 => InputPattern::Mixed,
+            4 => InputPattern::OnlyFirst,
+            5 => InputPattern::OnlyLast,
+            _ => InputPattern::AllButOne(random_range(rand, &(0..=input_count.saturating_sub(1)))),
+        }
+    }
+
+    fn includes<R: RngCore>(&self, rand: &mut R, index: usize, input_count: usize) -> bool {
+        match self {
+            InputPattern::Never => false,
+            InputPattern::Always => true,
+            InputPattern::Mixed => rand.next_u32().is_multiple_of(2),
+            InputPattern::OnlyFirst => index == 0,
+            InputPattern::OnlyLast => index + 1 == input_count,
+            InputPattern::AllButOne(excluded) => index != *excluded,
+        }
+    }
+}
+
+/// Like `random_bytes_lt` but at least one byte whenever `length` allows it
+fn random_bytes_nonempty<R: RngCore>(rand: &mut R, length: &RangeInclusive<usize>, max_bytes: &mut usize) -> Vec<u8> {
+    let mut result = random_bytes_lt(rand, length, max_bytes);
+
+    if result.is_empty() && *length.end() > 0 {
+        result = random_bytes_exact(rand, 1, max_bytes);
+    }
+
+    result
+}
+
+/// The scriptSig and witness of a single input
+///
+/// Whether the input has a witness and whether it has a scriptSig are chosen independently by the
+/// caller, so every combination can occur. Either one, when present, is non-empty unless the
+/// configured ranges only allow empty ones.
+fn random_spend<R: RngCore>(rand: &mut R, params: &GenerationParams, has_witness: bool, has_script_sig: bool, max_bytes: &mut usize) -> (ScriptBuf, Witness) {
+    let mut witness = Witness::new();
+
+    if params.style == FieldStyle::Realistic {
+        let script_sig = match (has_witness, has_script_sig) {
+            (true, false) => {
+                if rand.next_u32().is_multiple_of(2) {
+                    // P2WPKH
+                    let (signature, pubkey) = realistic_signature_and_pubkey(rand, max_bytes);
+                    witness.push(signature);
+                    witness.push(pubkey);
+                } else {
+                    // P2TR key path
+                    witness.push(random_bytes_exact(rand, 64, max_bytes));
+                }
+
+                ScriptBuf::new()
+            }
+            (true, true) => {
+                // P2SH-P2WPKH, the scriptSig pushes the witness program
+                let (signature, pubkey) = realistic_signature_and_pubkey(rand, max_bytes);
+                witness.push(signature);
+                witness.push(pubkey);
+
+                let mut script_sig = vec![0x16, 0x00, 0x14];
+                script_sig.extend(random_bytes_exact(rand, 20, max_bytes));
+
+                ScriptBuf::from_bytes(script_sig)
+            }
+            (false, true) => {
+                // P2PKH
+                let (signature, pubkey) = realistic_signature_and_pubkey(rand, max_bytes);
+
+                let mut script_sig = vec![signature.len() as u8];
+                script_sig.extend(signature);
+                script_sig.push(pubkey.len() as u8);
+                script_sig.extend(pubkey);
+
+                ScriptBuf::from_bytes(script_sig)
+            }
+            // Pay-to-anchor, spent with neither
+            (false, false) => ScriptBuf::new(),
+        };
+
+        return (script_sig, witness);
+    }
+
+    // A minimum length or count above zero rules out going without
+    let has_script_sig = has_script_sig || *params.script_sig_length.start() > 0;
+    let has_witness = has_witness || *params.witness_length.start() > 0;
+
+    // scriptSigs are short, so draw them first or large witnesses would usually exhaust the byte budget
+    let script_sig = if has_script_sig {
+        ScriptBuf::from_bytes(random_bytes_nonempty(rand, &params.script_sig_length, max_bytes))
+    } else {
+        ScriptBuf::new()
+    };
+
+    if has_witness {
+        let witness_item_count = max(random_count(rand, &params.witness_length, params.style), 1)
+            .min(*params.witness_length.end());
+
+        for _ in 0..witness_item_count {
+            let witness_item = random_witness_item(rand, params, max_bytes);
+            witness.push(witness_item);
+
+            if *max_bytes < 1 && witness.len() >= *params.witness_length.start() {
+                break;
+            }
+        }
+    }
+
+    (script_sig, witness)
+}
+
+fn random_script_pubkey<R: RngCore>(rand: &mut R, params: &GenerationParams, max_bytes: &mut usize) -> ScriptBuf {
+    match params.style {
+        FieldStyle::Realistic => realistic_script_pubkey(rand, max_bytes),
+        _ => ScriptBuf::from_bytes(random_bytes_lt(rand, &params.script_pubkey_length, max_bytes)),
+    }
+}
+
+pub fn random_tx<R: RngCore>(rand: &mut R, params: &GenerationParams) -> Transaction {
+    let version = random_version(rand, params.style);
+    let lock_time = random_lock_time(rand, params.style);
+
+    let input_count = random_count(rand, &params.input_count, params.style);
+    let output_count = random_count(rand, &params.output_count, params.style);
+
+    let mut random_bytes_remaining = random_range(rand, &params.random_bytes_count);
+
+    // Generate inputs, their scriptSigs and witnesses are filled in below once the count is known
+    let mut input: Vec<TxIn> = Vec::new();
+    for _ in 0..input_count {
+        let mut txid = [0u8; 32];
+        rand.fill_bytes(txid.as_mut());
+
+        let previous_output = OutPoint {
+            txid: Txid::hash(txid.as_ref()),
+            vout: rand.next_u32(),
+        };
+
+        random_bytes_remaining = random_bytes_remaining.saturating_sub(36);
+
+        input.push(TxIn {
+            previous_output,
+            script_sig: ScriptBuf::new(),
+            sequence: random_sequence(rand, params.style),
+            witness: Witness::new(),
+        });
+
+        // Running out of bytes ends the transaction early, but never below the minimum count
+        if random_bytes_remaining < 1 && input.len() >= *params.input_count.start() {
+            break;
+        }
+    }
+
+    let witness_pattern = InputPattern::random(rand, input.len());
+    let script_sig_pattern = InputPattern::random(rand, input.len());
+
+    let input_count = input.len();
+    for (index, txin) in input.iter_mut().enumerate() {
+        let has_witness = witness_pattern.includes(rand, index, input_count);
+        let has_script_sig = script_sig_pattern.includes(rand, index, input_count);
+
+        (txin.script_sig, txin.witness) = random_spend(rand, params, has_witness, has_script_sig, &mut random_bytes_remaining);
+    }
+
+    // Generate outputs
+    let mut output: Vec<TxOut> = Vec::new();
+    for _ in 0..output_count {
+        let value = random_amount(rand, params.style);
+
+        let script_pubkey = random_script_pubkey(rand, params, &mut random_bytes_remaining);
+
+        output.push(TxOut {
+            value,
+            script_pubkey,
+        });
+
+        if random_bytes_remaining < 1 && output.len() >= *params.output_count.start() {
+            break;
+        }
+    }
+
+    let mut tx = Transaction {
+        version,
+        lock_time,
+        input,
+        output,
+    };
+
+    if let Some(weight) = params.weight.as_ref() {
+        let target = random_range(rand, weight);
+
+        fit_weight(rand, &mut tx, params, target);
+    }
+
+    tx
+}
+
+/// Drop outputs then inputs while `tx` is heavier than `target` WU, then add outputs with
+/// scriptPubKeys sized to close the gap
+///
+/// Witness bytes count 1 WU and everything else 4 WU, so padding lands within a few WU of
+/// `target`. Minimum counts are kept even if that leaves `tx` too heavy.
+fn fit_weight<R: RngCore>(rand: &mut R, tx: &mut Transaction, params: &GenerationParams, target: usize) {
+    let weight = |tx: &Transaction| tx.weight().to_wu() as usize;
+
+    while weight(tx) > target && tx.output.len() > *params.output_count.start() {
+        tx.output.pop();
+    }
+
+    while weight(tx) > target && tx.input.len() > max(*params.input_count.start(), 1) {
+        tx.input.pop();
+    }
+
+    loop {
+        let gap = (target.saturating_sub(weight(tx))) / 4;
+
+        // An output is its 8 byte value, the script length and the script
+        let length = match gap {
+            0..=8 => break,
+            9..=261 => gap - 9,
+            _ => (gap - 11).min(MAX_SCRIPT_SIZE),
+        };
+
+        let mut script_pubkey = vec![0u8; length];
+        rand.fill_bytes(&mut script_pubkey);
+
+        tx.output.push(TxOut {
+            value: random_amount(rand, params.style),
+            script_pubkey: ScriptBuf::from_bytes(script_pubkey),
+        });
+    }
+}
+
+/// A random transaction whose output values span the full 64 bit range rather than `MAX_MONEY`
+pub fn random_raw_tx<R: RngCore>(rand: &mut R, params: &GenerationParams) -> RawTransaction {
+    let mut tx = RawTransaction::from(&random_tx(rand, params));
+
+    for output in tx.output.iter_mut() {
+        output.value = rand.next_u64();
+    }
+
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::seed::Seed;
+
+    /// Write `contents` to a config file unique to `name`
+    fn config(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("ctv-vectors-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, contents).expect("write config");
+
+        path
+    }
+
+    #[test]
+    fn parse_range_forms() {
+        assert_eq!(parse_range("1..=5"), Ok(1..=5));
+        assert_eq!(parse_range(" 2 ..= 3 "), Ok(2..=3));
+        assert_eq!(parse_range("7"), Ok(7..=7));
+        assert_eq!(parse_range("0..=0"), Ok(0..=0));
+    }
+
+    #[test]
+    fn parse_range_rejects() {
+        assert!(parse_range("5..=1").is_err());
+        assert!(parse_range("1..5").is_err());
+        assert!(parse_range("-1..=5").is_err());
+        assert!(parse_range("a").is_err());
+        assert!(parse_range("").is_err());
+    }
+
+    #[test]
+    fn with_file_overrides_only_what_it_mentions() {
+        let path = config("merge", r#"{"output_count": "0..=2", "style": "adversarial", "weight": "1000..=2000"}"#);
+        let params = Profile::Minimal.params().with_file(&path);
+        std::fs::remove_file(&path).expect("remove config");
+
+        let params = params.expect("valid config");
+        let minimal = Profile::Minimal.params();
+
+        assert_eq!(params.output_count, 0..=2);
+        assert_eq!(params.style, FieldStyle::Adversarial);
+        assert_eq!(params.weight, Some(1_000..=2_000));
+        assert_eq!(params.input_count, minimal.input_count);
+        assert_eq!(params.script_pubkey_length, minimal.script_pubkey_length);
+        assert_eq!(params.random_bytes_count, minimal.random_bytes_count);
+    }
+
+    #[test]
+    fn with_file_rejects_bad_configs() {
+        for (name, contents) in [
+            ("unknown", r#"{"input_cuont": "1..=2"}"#),
+            ("range", r#"{"input_count": "3..=1"}"#),
+            ("zero-inputs", r#"{"input_count": "0..=1"}"#),
+            ("syntax", r#"{"input_count": "#),
+        ] {
+            let path = config(name, contents);
+            let params = GenerationParams::default().with_file(&path);
+            std::fs::remove_file(&path).expect("remove config");
+
+            assert!(params.is_err(), "accepted {}", contents);
+        }
+    }
+
+    #[test]
+    fn random_bytes_honor_the_minimum_length() {
+        let mut rand = Seed::from(1).rng();
+
+        for budget in [0, 1, 150, 10_000] {
+            for _ in 0..100 {
+                let mut max_bytes = budget;
+                let bytes = random_bytes_lt(&mut rand, &(200..=210), &mut max_bytes);
+
+                assert!((200..=210).contains(&bytes.len()), "{} bytes from a budget of {}", bytes.len(), budget);
+            }
+        }
+    }
+}
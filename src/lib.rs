@@ -0,0 +1,31 @@
+//! Generate, load and check BIP-119 `DefaultCheckTemplateVerifyHash` test vectors
+//!
+//! The `rust-bitcoin-ctv-vectors` binary is a thin command line wrapper around this library, so
+//! everything it does can also be done from another crate's tests:
+//!
+//! ```
+//! use rust_bitcoin_ctv_vectors::{
+//!     generator::Profile,
+//!     oracle::NativeOracle,
+//!     seed::Seed,
+//!     vectors::CtvTestVector,
+//! };
+//!
+//! let params = Profile::Minimal.params();
+//! let vector = CtvTestVector::generate(Seed::from(42), &params, false, &NativeOracle);
+//!
+//! assert_eq!(vector.spend_index.len(), vector.result.len());
+//! ```
+
+pub mod coverage;
+pub mod ctv;
+pub mod diff;
+pub mod edge_cases;
+pub mod generator;
+pub mod metamorphic;
+pub mod oracle;
+pub mod raw;
+pub mod seed;
+pub mod shrink;
+pub mod upstream;
+pub mod vectors;
@@ -1,325 +1,1033 @@
 use bitcoin::{
-    blockdata::locktime::absolute::LockTime as AbsoluteLockTime,
-    Amount,
     consensus::encode::deserialize_hex,
     hashes::Hash,
-    OutPoint,
-    ScriptBuf,
-    Sequence,
-    consensus::encode::serialize_hex,
+    hex::DisplayHex,
     Transaction,
-    Txid,
-    TxIn,
-    TxOut,
-    blockdata::transaction::Version,
-    Witness,
 };
 
-use bitcoincore_rpc::{
-    Client,
-    RpcApi,
-    Auth,
+use clap::{
+    error::ErrorKind,
+    Args,
+    CommandFactory,
+    Parser,
+    Subcommand,
 };
 
-use clap::Parser;
-
-use rand::{
-    RngCore,
-    SeedableRng,
+use rust_bitcoin_ctv_vectors::{
+    coverage::{
+        coverage_tx,
+        Coverage,
+    },
+    ctv,
+    diff::{
+        self,
+        Disagreement,
+    },
+    edge_cases::{
+        amount_edge_cases,
+        edge_cases,
+        edge_spend_indices,
+        raw_amount_edge_cases,
+        AMOUNT_ABOVE_MAX_MONEY,
+    },
+    generator::{
+        parse_range,
+        GenerationParams,
+        Profile,
+    },
+    metamorphic::{
+        metamorphic_base,
+        metamorphic_group,
+    },
+    oracle::{
+        Oracle,
+        OracleKind,
+        OracleSpec,
+    },
+    raw::RawTransaction,
+    seed::Seed,
+    upstream,
+    vectors::{
+        load_entries,
+        seeded_tx,
+        Components,
+        CtvTestVector,
+        CtvTestVectorEntry,
+        Format,
+        Metadata,
+    },
 };
 
-use rand_chacha::ChaCha20Rng;
-
-use serde::Serialize;
-
-use std::cmp::max;
 use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-/// Generate a random integer in a given range
-fn random_range<R: RngCore>(rand: &mut R, range: &RangeInclusive<usize>) -> usize {
-    let x = rand.next_u64() as usize;
-    let size = max(range.end() - range.start(), 0) + 1;
-
-    range.start() + (x % size)
+/// `Write`-able output sink for either stdout or a filesystem file
+enum OutputDestination {
+    Stdout(std::io::Stdout),
+    File(std::fs::File),
 }
 
-const INPUT_COUNT: RangeInclusive<usize> = 1..=129;
-const OUTPUT_COUNT: RangeInclusive<usize> = 0..=129;
+impl std::io::Write for OutputDestination {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputDestination::Stdout(stdout) => stdout.write(buf),
+            OutputDestination::File(file) => file.write(buf),
+        }
+    }
 
-const SCRIPT_PUBKEY_LENGTH: RangeInclusive<usize> = 0..=129;
-const SCRIPT_SIG_LENGTH: RangeInclusive<usize> = 0..=129;
-const WITNESS_LENGTH: RangeInclusive<usize> = 0..=129;
-const WITNESS_ITEM_LENGTH: RangeInclusive<usize> = 0..=520;
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputDestination::Stdout(stdout) => stdout.flush(),
+            OutputDestination::File(file) => file.flush(),
+        }
+    }
+}
 
-/// An approximate amount of random bytes in the transaction
-/// Note that this doesn't account for things like VarInt lengths
-const RANDOM_BYTES_COUNT: RangeInclusive<usize> = 0..=10_000;
+impl std::str::FromStr for OutputDestination {
+    type Err = std::io::Error;
 
-/// Generate a random number of random bytes, no more than max_bytes
-fn random_bytes_lt<R: RngCore>(rand: &mut R, length: &RangeInclusive<usize>, max_bytes: &mut usize) -> Vec<u8> {
-    let mut result = Vec::new();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            Ok(Self::Stdout(std::io::stdout()))
+        } else {
+            let out_path = PathBuf::from_str(s)
+                .expect("infallible");
 
-    if *max_bytes < 1 {
-        return result;
+            std::fs::File::options()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(out_path)
+                .map(Self::File)
+        }
     }
+}
+
+#[derive(Args)]
+struct OracleArguments {
+    #[arg(long = "oracle", value_enum, default_value = "rpc")]
+    oracle: OracleKind,
+
+    #[arg(short = 'u', long = "rpc-url")]
+    url: Option<String>,
 
-    // Hacky, but should reduce the likelihood of generating lots of zero-length outputs on
-    // transactions, without biasing the average transaction size too much, hopefully.
-    let range = (*max_bytes + length.end() + 1) / 2;
+    #[arg(short = 'c', long = "cookie-file")]
+    cookie: Option<PathBuf>,
+
+    /// Command run by `--oracle process` or `--oracle json-lines`
+    #[arg(long = "oracle-command")]
+    command: Option<String>,
+
+    /// Seconds `--oracle json-lines` waits for each answer before restarting the command
+    #[arg(long = "oracle-timeout", default_value = "10")]
+    timeout: u64,
+}
+
+impl OracleArguments {
+    fn open(&self) -> Box<dyn Oracle> {
+        let exit = |message: &str| -> ! {
+            CommandLineArguments::command()
+                .error(ErrorKind::MissingRequiredArgument, message)
+                .exit()
+        };
 
-    let length = random_range(rand, length) % (range + 1);
+        let command = || self.command.clone()
+            .unwrap_or_else(|| exit("--oracle process and --oracle json-lines require --oracle-command"));
 
-    *max_bytes = max_bytes.saturating_sub(length);
+        let spec = match self.oracle {
+            OracleKind::Rpc => {
+                let (Some(url), Some(cookie)) = (self.url.clone(), self.cookie.clone()) else {
+                    exit("--oracle rpc requires --rpc-url and --cookie-file");
+                };
 
-    result.resize(length, 0);
+                OracleSpec::Rpc { url, cookie }
+            }
+            OracleKind::Native => OracleSpec::Native,
+            OracleKind::Process => OracleSpec::Process(command()),
+            OracleKind::JsonLines => OracleSpec::JsonLines(command()),
+        };
+
+        spec.open(Duration::from_secs(self.timeout))
+            .unwrap_or_else(|e| exit(&e))
+    }
+}
 
-    rand.fill_bytes(result.as_mut());
+#[derive(Args)]
+struct FormatArguments {
+    /// `upstream` for exactly the keys of upstream `ctvhash.json`, `extended` for everything
+    #[arg(long = "format", value_enum, default_value = "extended")]
+    format: Format,
+}
 
-    result
+#[derive(Args)]
+struct SeedArguments {
+    /// Seed for the run, 64 hex digits or a decimal u64 (random if omitted)
+    #[arg(short = 's', long = "seed")]
+    seed: Option<Seed>,
 }
 
-fn random_witness_item<R: RngCore>(rand: &mut R, max_bytes: &mut usize) -> Vec<u8> {
-    random_bytes_lt(rand, &WITNESS_ITEM_LENGTH, max_bytes)
+impl SeedArguments {
+    /// The given seed or a random one, printed to stderr since not every output records it
+    fn seed(&self) -> Seed {
+        let seed = self.seed.unwrap_or_else(Seed::random);
+        eprintln!("seed {}", seed);
+
+        seed
+    }
 }
 
-fn random_tx<R: RngCore>(rand: &mut R) -> Transaction {
-    let version = Version::non_standard(rand.next_u32() as i32);
-    let lock_time = AbsoluteLockTime::from_consensus(rand.next_u32());
+/// Overrides for the ranges that shape random transactions, written `MIN..=MAX` or `N`
+#[derive(Args)]
+struct GenerationArguments {
+    /// Preset ranges and field style, refined by `--config` and the options below
+    #[arg(long = "profile", value_enum, default_value = "default")]
+    profile: Profile,
 
-    let input_count = random_range(rand, &INPUT_COUNT);
-    let output_count = random_range(rand, &OUTPUT_COUNT);
+    /// JSON file of ranges to use instead of the defaults, overridden by the options below
+    #[arg(long = "config")]
+    config: Option<PathBuf>,
 
-    let mut random_bytes_remaining = random_range(rand, &RANDOM_BYTES_COUNT);
+    #[arg(long = "input-count", value_parser = parse_range)]
+    input_count: Option<RangeInclusive<usize>>,
 
-    let has_witness = (rand.next_u32() % 2) == 1;
+    #[arg(long = "output-count", value_parser = parse_range)]
+    output_count: Option<RangeInclusive<usize>>,
 
-    // Generate inputs
-    let mut input: Vec<TxIn> = Vec::new();
-    for _ in 0..input_count {
-        let mut txid = [0u8; 32];
-        rand.fill_bytes(txid.as_mut());
+    #[arg(long = "script-pubkey-length", value_parser = parse_range)]
+    script_pubkey_length: Option<RangeInclusive<usize>>,
 
-        let previous_output = OutPoint {
-            txid: Txid::hash(txid.as_ref()),
-            vout: rand.next_u32(),
-        };
+    #[arg(long = "script-sig-length", value_parser = parse_range)]
+    script_sig_length: Option<RangeInclusive<usize>>,
 
-        random_bytes_remaining = random_bytes_remaining.saturating_sub(36);
+    #[arg(long = "witness-length", value_parser = parse_range)]
+    witness_length: Option<RangeInclusive<usize>>,
 
-        let mut witness = Witness::new();
+    #[arg(long = "witness-item-length", value_parser = parse_range)]
+    witness_item_length: Option<RangeInclusive<usize>>,
 
-        if has_witness {
-            let witness_item_count = random_range(rand, &WITNESS_LENGTH);
+    /// Approximate number of random bytes in each transaction
+    #[arg(long = "random-bytes", value_parser = parse_range)]
+    random_bytes_count: Option<RangeInclusive<usize>>,
 
-            for _ in 0..witness_item_count {
-                let witness_item = random_witness_item(rand, &mut random_bytes_remaining);
-                witness.push(witness_item);
+    /// Weight in WU to trim or pad each transaction to
+    #[arg(long = "weight", value_parser = parse_range)]
+    weight: Option<RangeInclusive<usize>>,
+}
 
-                if random_bytes_remaining < 1 {
-                    break;
-                }
+impl GenerationArguments {
+    /// Whether any option changes the ranges from the default profile's
+    fn is_given(&self) -> bool {
+        self.profile != Profile::Default
+            || self.config.is_some()
+            || self.input_count.is_some()
+            || self.output_count.is_some()
+            || self.script_pubkey_length.is_some()
+            || self.script_sig_length.is_some()
+            || self.witness_length.is_some()
+            || self.witness_item_length.is_some()
+            || self.random_bytes_count.is_some()
+            || self.weight.is_some()
+    }
+
+    fn params(&self) -> GenerationParams {
+        let exit = |message: String| -> ! {
+            CommandLineArguments::command()
+                .error(ErrorKind::InvalidValue, message)
+                .exit()
+        };
+
+        let mut params = self.profile.params();
+
+        if let Some(path) = self.config.as_ref() {
+            params = params.with_file(path).unwrap_or_else(|e| exit(e));
+        }
+
+        let overrides = [
+            (&self.input_count, &mut params.input_count),
+            (&self.output_count, &mut params.output_count),
+            (&self.script_pubkey_length, &mut params.script_pubkey_length),
+            (&self.script_sig_length, &mut params.script_sig_length),
+            (&self.witness_length, &mut params.witness_length),
+            (&self.witness_item_length, &mut params.witness_item_length),
+            (&self.random_bytes_count, &mut params.random_bytes_count),
+        ];
+
+        for (value, param) in overrides {
+            if let Some(value) = value {
+                *param = value.clone();
             }
         }
 
-        let script_sig = if has_witness {
-            ScriptBuf::from_bytes(random_bytes_lt(rand, &SCRIPT_SIG_LENGTH, &mut random_bytes_remaining))
-        } else {
-            ScriptBuf::new()
-        };
+        if let Some(weight) = self.weight.as_ref() {
+            params.weight = Some(weight.clone());
+        }
 
-        input.push(TxIn {
-            previous_output,
-            script_sig,
-            sequence: Sequence::from_consensus(rand.next_u32()),
-            witness,
-        });
+        params.validate().unwrap_or_else(|e| exit(e));
+
+        params
+    }
+}
+
+#[derive(Args)]
+struct GenerateArguments {
+    #[command(flatten)]
+    oracle: OracleArguments,
+
+    #[command(flatten)]
+    generation: GenerationArguments,
+
+    #[arg(short = 'n', long = "transaction-count", default_value = "100")]
+    transaction_count: usize,
+
+    #[arg(short = 'o', long = "out-file", default_value = "-")]
+    out_path: String,
+
+    #[command(flatten)]
+    format: FormatArguments,
 
-        if random_bytes_remaining < 1 {
-            break;
+    #[command(flatten)]
+    seed: SeedArguments,
+
+    /// Instead of `-n` vectors, generate until every coverage bucket holds at least this many
+    #[arg(long = "coverage")]
+    coverage: Option<usize>,
+
+    /// Give up on coverage after trying this many candidate transactions
+    #[arg(long = "max-attempts", default_value = "1000000")]
+    max_attempts: u64,
+
+    /// Also emit the catalog of hand-built serialization boundary transactions
+    #[arg(long = "edge-cases")]
+    edge_cases: bool,
+
+    /// Also emit hand-built transactions with boundary output amounts and sums
+    #[arg(long = "amount-edges")]
+    amount_edges: bool,
+
+    /// Also emit this many random transactions with amounts up to `u64::MAX`, plus hand-built ones
+    /// above `MAX_MONEY`, tagged amount-above-max-money when an output exceeds it
+    #[arg(long = "raw-amounts")]
+    raw_amounts: Option<usize>,
+
+    /// Also emit the metamorphic groups of this many random transactions, one mutation per field
+    #[arg(long = "metamorphic")]
+    metamorphic: Option<usize>,
+
+    /// Include the intermediate hashes, counts and preimage of every vector
+    #[arg(long = "components")]
+    components: bool,
+}
+
+#[derive(Args)]
+struct VerifyArguments {
+    #[command(flatten)]
+    oracle: OracleArguments,
+
+    /// Test vector file to check, `-` for stdin
+    #[arg(default_value = "-")]
+    in_path: String,
+}
+
+#[derive(Args)]
+struct RegenArguments {
+    #[command(flatten)]
+    oracle: OracleArguments,
+
+    /// Must match the ranges of the original run, unless given by `--from`
+    #[command(flatten)]
+    generation: GenerationArguments,
+
+    /// Per-vector seed, as recorded in the vector's `seed` field
+    #[arg(short = 's', long = "seed")]
+    seed: Seed,
+
+    /// Vector file the seed comes from, whose header gives the ranges and mode of the original run
+    ///
+    /// The rebuilt vector is checked against the one in the file.
+    #[arg(long = "from", conflicts_with_all = ["coverage", "raw_amounts", "metamorphic"])]
+    from: Option<PathBuf>,
+
+    /// The vector came from a `--coverage` run
+    #[arg(long = "coverage", conflicts_with_all = ["raw_amounts", "metamorphic"])]
+    coverage: bool,
+
+    /// The vector is one of the random `--raw-amounts` vectors
+    #[arg(long = "raw-amounts", conflicts_with = "metamorphic")]
+    raw_amounts: bool,
+
+    /// The vector is the base of a `--metamorphic` group
+    #[arg(long = "metamorphic")]
+    metamorphic: bool,
+
+    /// Include the intermediate hashes, counts and preimage
+    #[arg(long = "components")]
+    components: bool,
+
+    #[arg(short = 'o', long = "out-file", default_value = "-")]
+    out_path: String,
+
+    #[command(flatten)]
+    format: FormatArguments,
+}
+
+#[derive(Args)]
+struct ConvertUpstreamArguments {
+    #[command(flatten)]
+    oracle: OracleArguments,
+
+    /// Upstream `bip-0119/vectors/ctvhash.json`, `-` for stdin
+    #[arg(default_value = "-")]
+    in_path: String,
+
+    #[arg(short = 'o', long = "out-file", default_value = "-")]
+    out_path: String,
+
+    #[command(flatten)]
+    format: FormatArguments,
+}
+
+#[derive(Args)]
+struct DiffArguments {
+    /// An oracle to compare, given at least twice: `native`, `rpc:<url>,<cookie-file>`,
+    /// `process:<command>` or `json-lines:<command>`
+    #[arg(long = "oracle", required = true)]
+    oracles: Vec<OracleSpec>,
+
+    /// Seconds `json-lines` oracles wait for each answer before restarting the command
+    #[arg(long = "oracle-timeout", default_value = "10")]
+    timeout: u64,
+
+    #[command(flatten)]
+    generation: GenerationArguments,
+
+    #[arg(short = 'n', long = "transaction-count", default_value = "100")]
+    transaction_count: usize,
+
+    #[command(flatten)]
+    seed: SeedArguments,
+
+    /// Generate the steered transactions of a `--coverage` run instead
+    #[arg(long = "coverage")]
+    coverage: bool,
+
+    /// Stop at the first disagreement instead of recording every one
+    #[arg(long = "stop")]
+    stop: bool,
+
+    /// Record each disagreement shrunk to a minimal transaction the oracles still disagree on
+    #[arg(long = "shrink")]
+    shrink: bool,
+
+    /// Where the disagreements are written, as a JSON array
+    #[arg(short = 'o', long = "out-file", default_value = "-")]
+    out_path: String,
+}
+
+#[derive(Args)]
+struct ExplainArguments {
+    /// Serialized transaction, in hex
+    hex_tx: String,
+
+    /// Input index the transaction is spent at
+    index: u32,
+}
+
+#[derive(Args)]
+struct SchemaArguments {
+    #[command(flatten)]
+    format: FormatArguments,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate random test vectors
+    Generate(GenerateArguments),
+    /// Recompute every hash in an existing test vector file and report mismatches
+    Verify(VerifyArguments),
+    /// Rebuild a single test vector from its per-vector seed
+    Regen(RegenArguments),
+    /// Clamp upstream amounts to `MAX_MONEY`, recompute the hashes and report any that changed
+    ConvertUpstream(ConvertUpstreamArguments),
+    /// Run random transactions through several oracles and record every disagreement
+    Diff(DiffArguments),
+    /// Print the template hash preimage of a transaction field by field
+    Explain(ExplainArguments),
+    /// Print the JSON Schema of the vector files written in a format
+    Schema(SchemaArguments),
+}
+
+// Without a subcommand, the arguments of `generate` are taken at the top level
+#[derive(Parser)]
+#[command(about, args_conflicts_with_subcommands = true)]
+struct CommandLineArguments {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    generate: GenerateArguments,
+}
+
+fn generate(args: GenerateArguments) {
+    let oracle = args.oracle.open();
+    let params = args.generation.params();
+
+    let seed = args.seed.seed();
+
+    let out = OutputDestination::from_str(args.out_path.as_ref()).expect("Open out");
+
+    let mut entries = Vec::new();
+
+    entries.push(CtvTestVectorEntry::Metadata(Metadata {
+        coverage: args.coverage,
+        ..Metadata::new(seed, args.generation.profile, &params, oracle.as_ref())
+    }));
+
+    let catalog = args.edge_cases.then(edge_cases).into_iter()
+        .chain(args.amount_edges.then(amount_edge_cases))
+        .flatten();
+
+    for edge_case in catalog {
+        let vector = CtvTestVector::from_tx(&edge_case.tx, edge_spend_indices(edge_case.tx.input.len()), oracle.as_ref());
+
+        entries.push(CtvTestVectorEntry::TestVector(CtvTestVector {
+            tags: edge_case.tags,
+            ..vector
+        }));
+    }
+
+    if let Some(raw_count) = args.raw_amounts {
+        for edge_case in raw_amount_edge_cases() {
+            let vector = CtvTestVector::from_raw_tx(&edge_case.tx, edge_spend_indices(edge_case.tx.input.len()), oracle.as_ref());
+
+            entries.push(CtvTestVectorEntry::TestVector(CtvTestVector {
+                tags: edge_case.tags,
+                ..vector
+            }));
+        }
+
+        // A separate stream, so these don't repeat the transactions of the main run
+        let raw_seed = seed.child(u64::MAX);
+
+        for n in 0..raw_count {
+            let vector = CtvTestVector::generate_raw(raw_seed.child(n as u64), &params, oracle.as_ref());
+
+            entries.push(CtvTestVectorEntry::TestVector(vector));
         }
     }
 
-    // Generate outputs
-    let sats_modulus = Amount::MAX_MONEY.to_sat() + 1;
-    let mut output: Vec<TxOut> = Vec::new();
-    for _ in 0..output_count {
-        let value = Amount::from_sat(rand.next_u64() % sats_modulus);
+    if let Some(metamorphic_count) = args.metamorphic {
+        // A separate stream, like the raw amount vectors
+        let metamorphic_seed = seed.child(u64::MAX - 1);
 
-        let script_pubkey_bytes = random_bytes_lt(rand, &SCRIPT_PUBKEY_LENGTH, &mut random_bytes_remaining);
+        for n in 0..metamorphic_count {
+            let group = metamorphic_group(metamorphic_seed.child(n as u64), &params, oracle.as_ref());
 
-        output.push(TxOut {
-            value,
-            script_pubkey: ScriptBuf::from_bytes(script_pubkey_bytes),
-        });
+            entries.push(CtvTestVectorEntry::Metamorphic(Box::new(group)));
+        }
+    }
+
+    match args.coverage {
+        None => {
+            for n in 0..args.transaction_count {
+                let vector = CtvTestVector::generate(seed.child(n as u64), &params, false, oracle.as_ref());
+
+                entries.push(CtvTestVectorEntry::TestVector(vector));
+            }
+        }
+        Some(minimum) => {
+            let mut coverage = Coverage::new(&params);
+
+            let mut attempts = 0u64;
+            while !coverage.is_complete(minimum) && attempts < args.max_attempts {
+                let child_seed = seed.child(attempts);
+                attempts += 1;
+
+                // Cheap to rebuild, so only candidates that fill a bucket are sent to the oracle
+                let tx = coverage_tx(&mut child_seed.rng(), &params);
+
+                if coverage.is_needed(&tx, minimum) {
+                    coverage.add(&tx);
+
+                    let vector = CtvTestVector::generate(child_seed, &params, true, oracle.as_ref());
+
+                    entries.push(CtvTestVectorEntry::TestVector(vector));
+                }
+            }
+
+            if !coverage.is_complete(minimum) {
+                eprintln!("gave up after {} attempts without full coverage", attempts);
+            }
 
-        if random_bytes_remaining < 1 {
-            break;
+            coverage.write_table(&mut std::io::stderr(), minimum)
+                .expect("write coverage table");
         }
     }
 
-    Transaction {
-        version,
-        lock_time,
-        input,
-        output,
+    if args.components {
+        entries = entries.into_iter()
+            .map(CtvTestVectorEntry::with_components)
+            .collect();
     }
+
+    args.format.format.write_entries(out, entries)
+        .expect("write json");
 }
 
-/// `Write`-able output sink for either stdout or a filesystem file
-enum OutputDestination {
-    Stdout(std::io::Stdout),
-    File(std::fs::File),
+/// Read a vector file, `-` for stdin
+fn read_entries(in_path: &str) -> Vec<CtvTestVectorEntry> {
+    if in_path == "-" {
+        load_entries(std::io::stdin().lock())
+    } else {
+        let file = std::fs::File::open(in_path)
+            .expect("open vector file");
+
+        load_entries(file)
+    }.expect("parse vector file")
 }
 
-impl std::io::Write for OutputDestination {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        match self {
-            OutputDestination::Stdout(stdout) => stdout.write(buf),
-            OutputDestination::File(file) => file.write(buf),
+/// Check a single vector against the oracle, reporting failures as `label`, returning their number
+fn verify_vector(label: &str, vector: &CtvTestVector, oracle: &dyn Oracle) -> usize {
+    let tx = match RawTransaction::deserialize_hex(&vector.transaction) {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("{}: can't deserialize hex_tx: {}", label, e);
+            return 1;
         }
+    };
+
+    if vector.spend_index.len() != vector.result.len() {
+        eprintln!("{}: {} spend indices but {} results",
+            label, vector.spend_index.len(), vector.result.len());
+        return 1;
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        match self {
-            OutputDestination::Stdout(stdout) => stdout.flush(),
-            OutputDestination::File(file) => file.flush(),
+    let mut failures = 0usize;
+
+    let results = match vector.result_byte_order.unwrap_or_default() {
+        ctv::ByteOrder::Display => Ok(vector.result.clone()),
+        ctv::ByteOrder::Internal => vector.result.iter().map(|hash| ctv::reverse_hex(hash)).collect(),
+    };
+
+    // Everything below compares against the display order oracles return
+    let results: Vec<String> = match results {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}: {}", label, e);
+            return 1;
+        }
+    };
+
+    let encodings = [
+        ("result_display", &vector.result_display, ctv::ByteOrder::Display),
+        ("result_internal", &vector.result_internal, ctv::ByteOrder::Internal),
+    ];
+
+    for (name, encoded, byte_order) in encodings {
+        if encoded.is_empty() {
+            continue;
+        }
+
+        let expected: Vec<String> = results.iter()
+            .map(|hash| match byte_order {
+                ctv::ByteOrder::Display => hash.to_lowercase(),
+                ctv::ByteOrder::Internal => ctv::reverse_hex(hash).expect("checked above"),
+            })
+            .collect();
+
+        let actual: Vec<String> = encoded.iter().map(|hash| hash.to_lowercase()).collect();
+
+        if actual != expected {
+            eprintln!("{}: {} doesn't match result, expected {:?}", label, name, expected);
+            failures += 1;
         }
     }
-}
 
-impl std::str::FromStr for OutputDestination {
-    type Err = std::io::Error;
+    if let Some(components) = vector.components.as_ref() {
+        let expected = Components::new(&tx, &vector.spend_index);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "-" {
-            Ok(Self::Stdout(std::io::stdout()))
-        } else {
-            let out_path = PathBuf::from_str(s)
-                .expect("infallible");
+        if **components != expected {
+            eprintln!("{}: components don't match hex_tx, expected {:?}", label, expected);
+            failures += 1;
+        }
+    }
 
-            std::fs::File::options()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(out_path)
-                .map(|file| Self::File(file))
+    for (index, expected) in vector.spend_index.iter().zip(results.iter()) {
+        let actual = match oracle.template_hash(&tx, *index) {
+            Ok(actual) => actual,
+            Err(e) => {
+                eprintln!("{}: spend_index {}: {} failed: {}", label, index, oracle.name(), e);
+                failures += 1;
+                continue;
+            }
+        };
+
+        if !actual.eq_ignore_ascii_case(expected) {
+            eprintln!("{}: spend_index {}: expected {} got {}",
+                label, index, expected, actual);
+            failures += 1;
         }
     }
+
+    failures
 }
 
-#[derive(Debug, Serialize)]
-struct Desc {
-    #[serde(rename = "Inputs")]
-    inputs: u32,
+/// Check every vector in a file against the oracle, returning the number of failures
+fn verify(args: VerifyArguments) -> usize {
+    let oracle = args.oracle.open();
+
+    let entries = read_entries(&args.in_path);
 
-    #[serde(rename = "Outputs")]
-    outputs: u32,
+    let mut vector_count = 0usize;
+    let mut failures = 0usize;
 
-    #[serde(rename = "Witness")]
-    witness: bool,
+    for (entry_index, entry) in entries.iter().enumerate() {
+        match entry {
+            CtvTestVectorEntry::TestVector(vector) => {
+                vector_count += 1;
+                failures += verify_vector(&format!("entry {}", entry_index), vector, oracle.as_ref());
+            }
+            CtvTestVectorEntry::Metamorphic(group) => {
+                vector_count += 1 + group.mutations.len();
+                failures += verify_vector(&format!("entry {} base", entry_index), &group.base, oracle.as_ref());
+
+                for mutant in group.mutations.iter() {
+                    let label = format!("entry {} {:?}", entry_index, mutant.mutation);
+                    failures += verify_vector(&label, &mutant.mutated, oracle.as_ref());
+
+                    if !mutant.holds(&group.base) {
+                        eprintln!("{}: results break the {:?} relation", label, mutant.relation);
+                        failures += 1;
+                    }
+                }
+            }
+            CtvTestVectorEntry::Metadata(_) | CtvTestVectorEntry::Documentation(_) => {}
+        }
+    }
 
-    #[serde(rename = "Version")]
-    version: i32,
+    eprintln!("checked {} vectors, {} failures", vector_count, failures);
 
-    #[serde(rename = "scriptSigs")]
-    script_sigs: bool,
+    failures
 }
 
-#[derive(Debug, Serialize)]
-struct CtvTestVector {
-    #[serde(rename = "hex_tx")]
-    transaction: String,
+fn regen(args: RegenArguments) {
+    let exit = |message: String| -> ! {
+        CommandLineArguments::command()
+            .error(ErrorKind::InvalidValue, message)
+            .exit()
+    };
+
+    let oracle = args.oracle.open();
+
+    // Where the vector came from: its run's ranges, mode, and the vector as recorded
+    let (params, coverage, raw_amounts, metamorphic, recorded) = match args.from.as_ref() {
+        None => (args.generation.params(), args.coverage, args.raw_amounts, args.metamorphic, None),
+        Some(path) => {
+            if args.generation.is_given() {
+                exit("--from takes the ranges from the file, don't give --profile, --config or ranges".to_string());
+            }
+
+            let entries = read_entries(&path.to_string_lossy());
+
+            let metadata = entries.iter()
+                .find_map(|entry| match entry {
+                    CtvTestVectorEntry::Metadata(metadata) => Some(metadata),
+                    _ => None,
+                })
+                .unwrap_or_else(|| exit(format!("{} has no header", path.display())));
+
+            let params = metadata.params.clone()
+                .unwrap_or_else(|| metadata.profile.params());
+            let coverage = metadata.coverage.is_some();
+
+            let (recorded, metamorphic) = entries.into_iter()
+                .find_map(|entry| match entry {
+                    CtvTestVectorEntry::TestVector(vector) if vector.seed == Some(args.seed) => Some((vector, false)),
+                    CtvTestVectorEntry::Metamorphic(group) if group.base.seed == Some(args.seed) => Some((group.base, true)),
+                    _ => None,
+                })
+                .unwrap_or_else(|| exit(format!("{} has no vector with seed {}", path.display(), args.seed)));
+
+            let raw_amounts = recorded.tags.iter().any(|tag| tag == AMOUNT_ABOVE_MAX_MONEY);
+
+            (params, coverage, raw_amounts, metamorphic, Some(recorded))
+        }
+    };
+
+    let out = OutputDestination::from_str(args.out_path.as_ref()).expect("Open out");
+
+    let mut vector = if metamorphic {
+        metamorphic_base(args.seed, &params, oracle.as_ref())
+    } else if raw_amounts {
+        CtvTestVector::generate_raw(args.seed, &params, oracle.as_ref())
+    } else {
+        CtvTestVector::generate(args.seed, &params, coverage, oracle.as_ref())
+    };
+
+    if let Some(recorded) = recorded {
+        let matches = |vector: &CtvTestVector| {
+            vector.transaction == recorded.transaction && vector.spend_index == recorded.spend_index
+        };
+
+        // Raw amount vectors that happen to stay within `MAX_MONEY` carry no tag
+        if !matches(&vector) && !raw_amounts && !metamorphic {
+            vector = CtvTestVector::generate_raw(args.seed, &params, oracle.as_ref());
+        }
 
-    spend_index: Vec<u32>,
+        if !matches(&vector) {
+            exit(format!("the vector rebuilt from seed {} differs from the one in the file", args.seed));
+        }
+    }
 
-    result: Vec<String>,
+    let vector = if args.components {
+        vector.with_components()
+    } else {
+        vector
+    };
 
-    desc: Desc,
+    args.format.format.write_vector(out, vector)
+        .expect("write json");
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-enum CtvTestVectorEntry {
-    TestVector(CtvTestVector),
-    Documentation(String),
+/// Convert upstream vectors, returning the number of hashes the oracle disagrees with upstream on
+fn convert_upstream(args: ConvertUpstreamArguments) -> usize {
+    let oracle = args.oracle.open();
+
+    let entries = read_entries(&args.in_path);
+
+    let out = OutputDestination::from_str(args.out_path.as_ref()).expect("Open out");
+
+    let mut converted = Vec::new();
+    let mut repaired_count = 0usize;
+    let mut dropped_count = 0usize;
+    let mut mismatch_count = 0usize;
+
+    for (entry_index, entry) in entries.into_iter().enumerate() {
+        let vector = match entry {
+            CtvTestVectorEntry::TestVector(vector) => vector,
+            // Describes the original file, not the converted one
+            CtvTestVectorEntry::Metadata(_) => continue,
+            // Never in upstream files, and never hold amounts that need repair
+            metamorphic @ CtvTestVectorEntry::Metamorphic(_) => {
+                converted.push(metamorphic);
+                continue;
+            }
+            documentation @ CtvTestVectorEntry::Documentation(_) => {
+                converted.push(documentation);
+                continue;
+            }
+        };
+
+        if let Err(e) = deserialize_hex::<Transaction>(&vector.transaction) {
+            eprintln!("entry {}: rust-bitcoin rejects hex_tx: {}", entry_index, e);
+        }
+
+        let mut raw_tx = match RawTransaction::deserialize_hex(&vector.transaction) {
+            Ok(raw_tx) => raw_tx,
+            Err(e) => {
+                eprintln!("entry {}: dropped, not a transaction at all: {}", entry_index, e);
+                dropped_count += 1;
+                continue;
+            }
+        };
+
+        let clamped = upstream::clamp_amounts(&mut raw_tx);
+
+        if !clamped.is_empty() {
+            eprintln!("entry {}: clamped outputs {:?} to MAX_MONEY", entry_index, clamped);
+        }
+
+        let tx: Transaction = match deserialize_hex(&raw_tx.serialize_hex()) {
+            Ok(tx) => tx,
+            Err(e) => {
+                eprintln!("entry {}: dropped, rust-bitcoin still rejects it after repair: {}", entry_index, e);
+                dropped_count += 1;
+                continue;
+            }
+        };
+
+        let tags = if clamped.is_empty() {
+            Vec::new()
+        } else {
+            repaired_count += 1;
+            vec![upstream::CLAMPED_AMOUNTS.to_string()]
+        };
+
+        let recomputed = CtvTestVector::from_tx(&tx, vector.spend_index.clone(), oracle.as_ref());
+
+        // Clamped vectors are different transactions, upstream's hashes no longer apply to them
+        if clamped.is_empty() {
+            mismatch_count += upstream_mismatches(entry_index, &vector, &recomputed);
+        }
+
+        converted.push(CtvTestVectorEntry::TestVector(CtvTestVector {
+            tags,
+            ..recomputed
+        }));
+    }
+
+    eprintln!("repaired {} vectors, dropped {}, {} hashes differ from upstream's",
+        repaired_count, dropped_count, mismatch_count);
+
+    args.format.format.write_entries(out, converted)
+        .expect("write json");
+
+    mismatch_count
 }
 
-#[derive(Parser)]
-struct CommandLineArguments {
-    #[arg(short = 'u', long = "rpc-url")]
-    url: String,
+/// Report every spend index where `recomputed` doesn't have the hash upstream recorded in `vector`
+fn upstream_mismatches(entry_index: usize, vector: &CtvTestVector, recomputed: &CtvTestVector) -> usize {
+    if vector.result.len() != vector.spend_index.len() {
+        eprintln!("entry {}: {} spend indices but {} results",
+            entry_index, vector.spend_index.len(), vector.result.len());
+        return 1;
+    }
 
-    #[arg(short = 'c', long = "cookie-file")]
-    cookie: PathBuf,
+    let mut mismatches = 0usize;
 
-    #[arg(short = 'n', long = "transaction-count", default_value = "100")]
-    transaction_count: usize,
+    for ((index, recorded), result) in vector.spend_index.iter().zip(&vector.result).zip(&recomputed.result) {
+        let recorded = match vector.result_byte_order.unwrap_or_default() {
+            ctv::ByteOrder::Display => Ok(recorded.to_lowercase()),
+            ctv::ByteOrder::Internal => ctv::reverse_hex(recorded),
+        };
 
-    #[arg(short = 'o', long = "out-file", default_value = "-")]
-    out_path: String,
+        match recorded {
+            Ok(recorded) if recorded.eq_ignore_ascii_case(result) => {}
+            Ok(recorded) => {
+                eprintln!("entry {}: spend index {}: upstream has {}, oracle computed {}",
+                    entry_index, index, recorded, result);
+                mismatches += 1;
+            }
+            Err(e) => {
+                eprintln!("entry {}: spend index {}: {}", entry_index, index, e);
+                mismatches += 1;
+            }
+        }
+    }
+
+    mismatches
 }
 
-fn main() {
-    let args = CommandLineArguments::parse();
+/// Compare oracles on random transactions, returning the number of disagreements
+fn diff(args: DiffArguments) -> usize {
+    if args.oracles.len() < 2 {
+        CommandLineArguments::command()
+            .error(ErrorKind::TooFewValues, "diff needs at least two --oracle")
+            .exit();
+    }
 
-    let cookie = Auth::CookieFile(args.cookie.clone());
-    let client = Client::new(args.url.as_ref(), cookie)
-        .expect("open client");
+    let oracles: Vec<Box<dyn Oracle>> = args.oracles.iter()
+        .map(|spec| spec.open(Duration::from_secs(args.timeout)).expect("open oracle"))
+        .collect();
+    let params = args.generation.params();
 
-    let mut rng = ChaCha20Rng::from_os_rng();
+    let seed = args.seed.seed();
 
     let out = OutputDestination::from_str(args.out_path.as_ref()).expect("Open out");
 
-    let mut entries = Vec::new();
+    let mut disagreements: Vec<Disagreement> = Vec::new();
 
-    entries.push(CtvTestVectorEntry::Documentation(
-        "{\"hex_tx\":string (hex tx), \"spend_index\":[number], \"result\": [string (hex hash)]}"
-            .to_string()
-    ));
+    'transactions: for n in 0..args.transaction_count {
+        let child_seed = seed.child(n as u64);
+        let (tx, spend_index) = seeded_tx(child_seed, &params, args.coverage);
+        let tx = RawTransaction::from(&tx);
 
-    for _n in 0..args.transaction_count {
-        let tx = random_tx(&mut rng);
+        for index in spend_index {
+            if let Some(disagreement) = diff::compare(&oracles, &tx, index) {
+                eprintln!("transaction {} (seed {}): oracles disagree on spend_index {}", n, child_seed, index);
+                for answer in disagreement.answers.iter() {
+                    eprintln!("    {}: {:?}", answer.oracle, answer.answer);
+                }
 
-        let mut spend_index: Vec<u32> = vec![0, 1];
-        spend_index.extend((0..2).map(|_| rng.next_u32()));
+                let disagreement = if args.shrink {
+                    let shrunk = diff::shrink_disagreement(&oracles, tx.clone(), index);
 
-        let mut result: Vec<String> = Vec::new();
+                    eprintln!("    shrunk from {} to {} bytes, spend_index {}: {}",
+                        disagreement.transaction.len() / 2, shrunk.transaction.len() / 2, shrunk.spend_index, shrunk.transaction);
 
-        let hextx = serialize_hex(&tx);
+                    shrunk
+                } else {
+                    disagreement
+                };
 
-        let _deserialized_hex: Transaction = deserialize_hex(&hextx)
-            .expect("deserialize hex");
+                disagreements.push(Disagreement {
+                    seed: Some(child_seed),
+                    ..disagreement
+                });
 
-        let desc = Desc {
-            inputs: tx.input.len() as u32,
-            outputs: tx.output.len() as u32,
-            witness: tx.input.iter().any(|input| !input.witness.is_empty()),
-            version: tx.version.0,
-            script_sigs: tx.input.iter().any(|input| !input.script_sig.is_empty()),
-        };
+                if args.stop {
+                    break 'transactions;
+                }
+            }
+        }
+    }
 
-        for i in spend_index.iter() {
-            let default_template: String = client.call("getdefaulttemplate", &[
-                 hextx.clone().into(),
-                 (*i).into(),
-                 if desc.witness { true.into() } else { false.into() },
-            ]).unwrap();
+    eprintln!("{} disagreements", disagreements.len());
 
-            result.push(default_template);
-        }
+    serde_json::to_writer_pretty(out, &disagreements)
+        .expect("write json");
+
+    disagreements.len()
+}
+
+fn explain(args: ExplainArguments) {
+    let tx = RawTransaction::deserialize_hex(&args.hex_tx)
+        .unwrap_or_else(|e| {
+            CommandLineArguments::command()
+                .error(ErrorKind::InvalidValue, format!("can't deserialize hex_tx: {}", e))
+                .exit()
+        });
 
-        entries.push(CtvTestVectorEntry::TestVector(
-            CtvTestVector {
-                transaction: hextx,
-                spend_index,
-                result,
-                desc,
+    if let Err(e) = deserialize_hex::<Transaction>(&args.hex_tx) {
+        println!("note: rust-bitcoin rejects this transaction: {}", e);
+    }
+
+    println!("{:>6} {:>4}  {:<16} {:<64}  value", "offset", "size", "field", "bytes");
+
+    let mut offset = 0usize;
+
+    for field in ctv::preimage_fields(&tx, args.index) {
+        match field.bytes {
+            Some(bytes) => {
+                println!("{:>6} {:>4}  {:<16} {:<64}  {}",
+                    offset, bytes.len(), field.name, bytes.to_lower_hex_string(), field.value);
+
+                offset += bytes.len();
             }
-        ));
+            None => println!("{:>6} {:>4}  {:<16} {:<64}  {}", "-", 0, field.name, "", field.value),
+        }
     }
 
-    serde_json::to_writer_pretty(out, &entries)
-        .expect("write json");
+    let hash = ctv::default_template_hash(&tx, args.index);
+
+    println!();
+    println!("sha256 of {} preimage bytes: {}", offset, hash.to_byte_array().to_lower_hex_string());
+    println!("as getdefaulttemplate returns it (byte reversed): {}", ctv::template_hash_hex(&hash));
+}
+
+fn main() {
+    let args = CommandLineArguments::parse();
+
+    match args.command.unwrap_or(Command::Generate(args.generate)) {
+        Command::Generate(args) => generate(args),
+        Command::Verify(args) => {
+            if verify(args) > 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Regen(args) => regen(args),
+        Command::ConvertUpstream(args) => {
+            if convert_upstream(args) > 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Diff(args) => {
+            if diff(args) > 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Explain(args) => explain(args),
+        Command::Schema(args) => print!("{}", args.format.format.schema()),
+    }
 }
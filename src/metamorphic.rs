@@ -0,0 +1,190 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::generator::GenerationParams;
+use crate::oracle::Oracle;
+use crate::raw::RawTransaction;
+use crate::seed::Seed;
+use crate::vectors::{
+    seeded_tx,
+    CtvTestVector,
+};
+
+/// How the hashes of a metamorphic pair must compare
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Relation {
+    Equal,
+    Different,
+}
+
+impl Relation {
+    pub fn holds(&self, base: &str, mutated: &str) -> bool {
+        base.eq_ignore_ascii_case(mutated) == (*self == Relation::Equal)
+    }
+}
+
+/// A change to one field of a transaction, or to the index it's spent at
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mutation {
+    /// Not committed to: flip a byte of a witness item, or add one if there are none
+    WitnessItem,
+    /// Not committed to: the spent input's previous txid and vout
+    Outpoint,
+    SpendIndex,
+    OutputScript,
+    OutputAmount,
+    Sequence,
+    ScriptSig,
+    Version,
+    LockTime,
+}
+
+impl Mutation {
+    pub const ALL: [Mutation; 9] = [
+        Mutation::WitnessItem,
+        Mutation::Outpoint,
+        Mutation::SpendIndex,
+        Mutation::OutputScript,
+        Mutation::OutputAmount,
+        Mutation::Sequence,
+        Mutation::ScriptSig,
+        Mutation::Version,
+        Mutation::LockTime,
+    ];
+
+    /// Whether BIP-119 commits to the mutated field
+    pub fn relation(&self) -> Relation {
+        match self {
+            Mutation::WitnessItem | Mutation::Outpoint => Relation::Equal,
+            _ => Relation::Different,
+        }
+    }
+
+    /// Apply the mutation to `tx` spent at `index`, which must be one of its inputs
+    ///
+    /// Returns `None` if `tx` has nothing to mutate, i.e. output mutations without outputs.
+    pub fn apply(&self, tx: &RawTransaction, index: u32) -> Option<(RawTransaction, u32)> {
+        let mut tx = tx.clone();
+        let mut index = index;
+        let input = &mut tx.input[index as usize];
+
+        match self {
+            Mutation::WitnessItem => {
+                match tx.input.iter_mut().flat_map(|input| input.witness.iter_mut()).find(|item| !item.is_empty()) {
+                    Some(item) => item[0] ^= 1,
+                    None => tx.input[index as usize].witness.push(vec![0x01]),
+                }
+            }
+            Mutation::Outpoint => {
+                input.txid[0] ^= 1;
+                input.vout = input.vout.wrapping_add(1);
+            }
+            Mutation::SpendIndex => index = index.wrapping_add(1),
+            Mutation::OutputScript => {
+                let script_pubkey = &mut tx.output.first_mut()?.script_pubkey;
+
+                match script_pubkey.first_mut() {
+                    Some(byte) => *byte ^= 1,
+                    None => script_pubkey.push(0x51),
+                }
+            }
+            Mutation::OutputAmount => {
+                let output = tx.output.first_mut()?;
+
+                // Stays within `MAX_MONEY` if it already was
+                output.value = output.value.checked_sub(1).unwrap_or(1);
+            }
+            Mutation::Sequence => input.sequence ^= 1,
+            Mutation::ScriptSig => {
+                match input.script_sig.first_mut() {
+                    Some(byte) => *byte ^= 1,
+                    None => input.script_sig.push(0x51),
+                }
+            }
+            Mutation::Version => tx.version = tx.version.wrapping_add(1),
+            Mutation::LockTime => tx.lock_time ^= 1,
+        }
+
+        Some((tx, index))
+    }
+}
+
+/// A mutated copy of a metamorphic base, and how its hash must compare to the base's
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Mutant {
+    pub mutation: Mutation,
+    pub relation: Relation,
+    pub mutated: CtvTestVector,
+}
+
+impl Mutant {
+    /// Whether the expected results of `base` and the mutant satisfy its relation
+    pub fn holds(&self, base: &CtvTestVector) -> bool {
+        base.result.len() == 1
+            && self.mutated.result.len() == 1
+            && self.relation.holds(&base.result[0], &self.mutated.result[0])
+    }
+}
+
+/// A single-index vector and every applicable mutation of it, each one mutation apart from it
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetamorphicGroup {
+    pub base: CtvTestVector,
+    pub mutations: Vec<Mutant>,
+}
+
+/// The transaction generated from `seed`, and the input of it a metamorphic base spends
+///
+/// The input is chosen by the seed, so that mutations of the spent input are meaningful.
+fn base_tx(seed: Seed, params: &GenerationParams) -> (RawTransaction, u32) {
+    let (tx, spend_index) = seeded_tx(seed, params, false);
+    let tx = RawTransaction::from(&tx);
+    let index = spend_index[2] % tx.input.len() as u32;
+
+    (tx, index)
+}
+
+/// The base vector of the metamorphic group generated from `seed`
+pub fn metamorphic_base(seed: Seed, params: &GenerationParams, oracle: &dyn Oracle) -> CtvTestVector {
+    let (tx, index) = base_tx(seed, params);
+
+    CtvTestVector {
+        seed: Some(seed),
+        ..CtvTestVector::from_raw_tx(&tx, vec![index], oracle)
+    }
+}
+
+/// Every applicable mutation of the transaction generated from `seed`
+pub fn metamorphic_group(seed: Seed, params: &GenerationParams, oracle: &dyn Oracle) -> MetamorphicGroup {
+    let (tx, index) = base_tx(seed, params);
+
+    let base = CtvTestVector {
+        seed: Some(seed),
+        ..CtvTestVector::from_raw_tx(&tx, vec![index], oracle)
+    };
+
+    let mutations = Mutation::ALL.into_iter()
+        .filter_map(|mutation| {
+            let (mutated_tx, mutated_index) = mutation.apply(&tx, index)?;
+
+            let mutant = Mutant {
+                mutation,
+                relation: mutation.relation(),
+                mutated: CtvTestVector::from_raw_tx(&mutated_tx, vec![mutated_index], oracle),
+            };
+
+            assert!(mutant.holds(&base), "{:?} breaks the {:?} relation for seed {}", mutation, mutant.relation, seed);
+
+            Some(mutant)
+        })
+        .collect();
+
+    MetamorphicGroup {
+        base,
+        mutations,
+    }
+}
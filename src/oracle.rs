@@ -0,0 +1,403 @@
+use bitcoincore_rpc::{
+    Auth,
+    Client,
+    RpcApi,
+};
+
+use clap::ValueEnum;
+
+use crate::ctv;
+use crate::raw::RawTransaction;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use std::cell::RefCell;
+use std::io::{
+    BufRead,
+    BufReader,
+    Write,
+};
+use std::path::PathBuf;
+use std::process::{
+    Child,
+    Command,
+    Stdio,
+};
+use std::sync::mpsc::{
+    self,
+    Receiver,
+    RecvTimeoutError,
+    Sender,
+};
+use std::str::FromStr;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// Which implementation is trusted to produce the `result` hashes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OracleKind {
+    /// Bitcoin Core's `getdefaulttemplate` RPC (requires the ctv-rpc fork)
+    Rpc,
+    /// This crate's own BIP-119 implementation, no node required
+    Native,
+    /// An external command, run once per hash
+    Process,
+    /// A long running external command, speaking JSON lines on stdin and stdout
+    JsonLines,
+}
+
+/// Everything needed to open an oracle, written `native`, `rpc:<url>,<cookie-file>`,
+/// `process:<command>` or `json-lines:<command>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OracleSpec {
+    Rpc {
+        url: String,
+        cookie: PathBuf,
+    },
+    Native,
+    Process(String),
+    JsonLines(String),
+}
+
+impl OracleSpec {
+    /// `timeout` only applies to `json-lines` oracles
+    pub fn open(&self, timeout: Duration) -> Result<Box<dyn Oracle>, String> {
+        Ok(match self {
+            OracleSpec::Rpc { url, cookie } => {
+                let client = Client::new(url, Auth::CookieFile(cookie.clone()))
+                    .map_err(|e| format!("open client for {}: {}", url, e))?;
+
+                Box::new(RpcOracle::new(url, client))
+            }
+            OracleSpec::Native => Box::new(NativeOracle),
+            OracleSpec::Process(command) => Box::new(ProcessOracle::new(command)?),
+            OracleSpec::JsonLines(command) => Box::new(JsonLinesOracle::new(command, timeout)?),
+        })
+    }
+}
+
+impl FromStr for OracleSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (s, None),
+        };
+
+        let kind = OracleKind::from_str(name, false)?;
+
+        match (kind, argument) {
+            (OracleKind::Native, None) => Ok(OracleSpec::Native),
+            (OracleKind::Rpc, Some(argument)) => {
+                let (url, cookie) = argument.rsplit_once(',')
+                    .ok_or_else(|| format!("expected rpc:<url>,<cookie-file>, got {:?}", s))?;
+
+                Ok(OracleSpec::Rpc {
+                    url: url.to_string(),
+                    cookie: PathBuf::from(cookie),
+                })
+            }
+            (OracleKind::Process, Some(command)) => Ok(OracleSpec::Process(command.to_string())),
+            (OracleKind::JsonLines, Some(command)) => Ok(OracleSpec::JsonLines(command.to_string())),
+            (OracleKind::Native, Some(_)) => Err("native takes no argument".to_string()),
+            (_, None) => Err(format!("{} needs an argument after ':'", name)),
+        }
+    }
+}
+
+/// Source of ground truth `DefaultCheckTemplateVerifyHash` values
+pub trait Oracle {
+    /// Short description used when reporting failures
+    fn name(&self) -> String;
+
+    /// Compute the template hash of `tx` spent at `index`, as the hex `getdefaulttemplate` returns
+    fn template_hash(&self, tx: &RawTransaction, index: u32) -> Result<String, String>;
+
+    /// Version of the implementation behind the oracle, if it can tell
+    fn version(&self) -> Option<String> {
+        None
+    }
+}
+
+/// This crate's own implementation in `ctv`
+pub struct NativeOracle;
+
+impl Oracle for NativeOracle {
+    fn name(&self) -> String {
+        "native".to_string()
+    }
+
+    fn version(&self) -> Option<String> {
+        Some(env!("CARGO_PKG_VERSION").to_string())
+    }
+
+    fn template_hash(&self, tx: &RawTransaction, index: u32) -> Result<String, String> {
+        Ok(ctv::template_hash_hex(&ctv::default_template_hash(tx, index)))
+    }
+}
+
+/// A node running the ctv-rpc fork of Bitcoin Core
+pub struct RpcOracle {
+    url: String,
+    client: Client,
+}
+
+impl RpcOracle {
+    pub fn new(url: &str, client: Client) -> Self {
+        RpcOracle {
+            url: url.to_string(),
+            client,
+        }
+    }
+}
+
+impl Oracle for RpcOracle {
+    fn name(&self) -> String {
+        format!("rpc {}", self.url)
+    }
+
+    /// The node's user agent, e.g. `/Satoshi:27.0.0/`, from `getnetworkinfo`
+    fn version(&self) -> Option<String> {
+        // Forks add and remove fields, so don't insist on any but the one needed
+        let info: serde_json::Value = self.client.call("getnetworkinfo", &[]).ok()?;
+
+        info["subversion"].as_str().map(str::to_string)
+    }
+
+    fn template_hash(&self, tx: &RawTransaction, index: u32) -> Result<String, String> {
+        self.client.call("getdefaulttemplate", &[
+            tx.serialize_hex().into(),
+            index.into(),
+            tx.has_witness().into(),
+        ]).map_err(|e| format!("getdefaulttemplate: {}", e))
+    }
+}
+
+/// An external command, given `<hex_tx> <spend_index>` on a single stdin line
+///
+/// The command must print the hash on stdout and exit successfully. Anything on stderr is passed
+/// through.
+pub struct ProcessOracle {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ProcessOracle {
+    /// `command` is split on whitespace into the program and its arguments
+    pub fn new(command: &str) -> Result<Self, String> {
+        let (program, args) = split_command(command)?;
+
+        Ok(ProcessOracle {
+            program,
+            args,
+        })
+    }
+}
+
+impl Oracle for ProcessOracle {
+    fn name(&self) -> String {
+        format!("process {}", self.program)
+    }
+
+    fn template_hash(&self, tx: &RawTransaction, index: u32) -> Result<String, String> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("spawn {}: {}", self.program, e))?;
+
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        writeln!(stdin, "{} {}", tx.serialize_hex(), index)
+            .map_err(|e| format!("write to {}: {}", self.program, e))?;
+        drop(stdin);
+
+        let output = child.wait_with_output()
+            .map_err(|e| format!("wait for {}: {}", self.program, e))?;
+
+        if !output.status.success() {
+            return Err(format!("{} exited with {}", self.program, output.status));
+        }
+
+        let stdout = String::from_utf8(output.stdout)
+            .map_err(|e| format!("{} printed invalid UTF-8: {}", self.program, e))?;
+
+        Ok(stdout.trim().to_string())
+    }
+}
+
+/// Split `command` on whitespace into the program and its arguments
+fn split_command(command: &str) -> Result<(String, Vec<String>), String> {
+    let mut words = command.split_whitespace().map(str::to_string);
+
+    let program = words.next()
+        .ok_or_else(|| "empty oracle command".to_string())?;
+
+    Ok((program, words.collect()))
+}
+
+#[derive(Serialize)]
+struct JsonLinesRequest<'a> {
+    hex_tx: &'a str,
+    spend_index: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonLinesResponse {
+    Result(String),
+    Error(String),
+}
+
+/// A spawned `JsonLinesOracle` command, the requests queued for it and the lines it has printed
+struct JsonLinesProcess {
+    child: Child,
+    requests: Sender<String>,
+    lines: Receiver<std::io::Result<String>>,
+}
+
+impl Drop for JsonLinesProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A long running external command, sent one `{"hex_tx", "spend_index"}` JSON request per line
+///
+/// For each request the command must print one line, either `{"result": "<hash>"}` or
+/// `{"error": "<message>"}`. A command that exits or takes longer than the timeout to answer fails
+/// that request, and is restarted for the next one.
+pub struct JsonLinesOracle {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+    process: RefCell<Option<JsonLinesProcess>>,
+}
+
+impl JsonLinesOracle {
+    /// `command` is split on whitespace into the program and its arguments
+    pub fn new(command: &str, timeout: Duration) -> Result<Self, String> {
+        let (program, args) = split_command(command)?;
+
+        Ok(JsonLinesOracle {
+            program,
+            args,
+            timeout,
+            process: RefCell::new(None),
+        })
+    }
+
+    fn spawn(&self) -> Result<JsonLinesProcess, String> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("spawn {}: {}", self.program, e))?;
+
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+
+        // Writing and reading on other threads is the only portable way to give up on a hung
+        // command, a request bigger than the pipe buffer blocks until the command reads it
+        let (requests, pending) = mpsc::channel::<String>();
+        std::thread::spawn(move || {
+            for request in pending {
+                if writeln!(stdin, "{}", request).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(JsonLinesProcess {
+            child,
+            requests,
+            lines,
+        })
+    }
+
+    /// Send a single request to a running process and wait for its answer
+    ///
+    /// The outer error means the process can't be relied on anymore, the inner one is an error the
+    /// command reported for this request.
+    ///
+    /// Writing, reading and waiting for an exit all share a single `timeout`.
+    fn request(&self, process: &mut JsonLinesProcess, request: &str) -> Result<Result<String, String>, String> {
+        let deadline = Instant::now() + self.timeout;
+
+        process.requests.send(request.to_string())
+            .map_err(|_| format!("write to {}: stdin closed", self.program))?;
+
+        let line = match process.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => line.map_err(|e| format!("read from {}: {}", self.program, e))?,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(format!("{} gave no answer within {:?}", self.program, self.timeout));
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err(self.exit_status(process, deadline)),
+        };
+
+        match serde_json::from_str(&line) {
+            Ok(JsonLinesResponse::Result(hash)) => Ok(Ok(hash)),
+            Ok(JsonLinesResponse::Error(message)) => Ok(Err(format!("{} reported: {}", self.program, message))),
+            Err(e) => Err(format!("{} printed an invalid response {:?}: {}", self.program, line, e)),
+        }
+    }
+
+    /// Why a process closed its stdout, without waiting past `deadline` for it to exit
+    fn exit_status(&self, process: &mut JsonLinesProcess, deadline: Instant) -> String {
+        loop {
+            match process.child.try_wait() {
+                Ok(Some(status)) => return format!("{} exited with {}", self.program, status),
+                Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                Ok(None) => return format!("{} closed its stdout", self.program),
+                Err(e) => return format!("wait for {}: {}", self.program, e),
+            }
+        }
+    }
+}
+
+impl Oracle for JsonLinesOracle {
+    fn name(&self) -> String {
+        format!("json-lines {}", self.program)
+    }
+
+    fn template_hash(&self, tx: &RawTransaction, index: u32) -> Result<String, String> {
+        let hex_tx = tx.serialize_hex();
+        let request = serde_json::to_string(&JsonLinesRequest {
+            hex_tx: &hex_tx,
+            spend_index: index,
+        }).expect("serialize request");
+
+        let mut process = self.process.borrow_mut();
+
+        if process.is_none() {
+            *process = Some(self.spawn()?);
+        }
+
+        match self.request(process.as_mut().expect("just spawned"), &request) {
+            Ok(result) => result,
+            Err(e) => {
+                // A command in an unknown state can't be trusted with the next request, restart it
+                *process = None;
+
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use bitcoin::{
+    hashes::{
+        sha256d,
+        Hash,
+    },
+    hex::{
+        DisplayHex,
+        FromHex,
+    },
+    Transaction,
+    Txid,
+    Wtxid,
+};
+
+/// Transaction output whose value is any 64 bit integer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawTxOut {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawTxIn {
+    pub txid: [u8; 32],
+    pub vout: u32,
+    pub script_sig: Vec<u8>,
+    pub sequence: u32,
+    pub witness: Vec<Vec<u8>>,
+}
+
+/// A transaction serialized and parsed without any of rust-bitcoin's sanity checks
+///
+/// `bitcoin::Transaction` holds output values as `Amount`, whose range and parsing rules are up to
+/// rust-bitcoin. This holds exactly the bytes Bitcoin Core would hash, so it can express anything
+/// found in the upstream vectors, including values above `MAX_MONEY` up to `u64::MAX`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawTransaction {
+    pub version: i32,
+    pub lock_time: u32,
+    pub input: Vec<RawTxIn>,
+    pub output: Vec<RawTxOut>,
+}
+
+fn write_compact_size(out: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&(n as u64).to_le_bytes());
+        }
+    }
+}
+
+/// Append `bytes` prefixed with their CompactSize length
+pub fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_compact_size(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+impl RawTxOut {
+    /// Consensus serialization, as committed to by the outputs hash
+    pub fn serialize_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.value.to_le_bytes());
+        write_bytes(out, &self.script_pubkey);
+    }
+}
+
+impl RawTransaction {
+    pub fn has_witness(&self) -> bool {
+        self.input.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Consensus serialization, in the segwit format iff any input has a witness
+    pub fn serialize(&self) -> Vec<u8> {
+        self.serialize_with(self.has_witness())
+    }
+
+    /// Serialization without witnesses, as committed to by the txid
+    pub fn serialize_without_witness(&self) -> Vec<u8> {
+        self.serialize_with(false)
+    }
+
+    fn serialize_with(&self, has_witness: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        out.extend_from_slice(&self.version.to_le_bytes());
+
+        if has_witness {
+            out.extend_from_slice(&[0x00, 0x01]);
+        }
+
+        write_compact_size(&mut out, self.input.len());
+        for input in self.input.iter() {
+            out.extend_from_slice(&input.txid);
+            out.extend_from_slice(&input.vout.to_le_bytes());
+            write_bytes(&mut out, &input.script_sig);
+            out.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+
+        write_compact_size(&mut out, self.output.len());
+        for output in self.output.iter() {
+            output.serialize_into(&mut out);
+        }
+
+        if has_witness {
+            for input in self.input.iter() {
+                write_compact_size(&mut out, input.witness.len());
+                for item in input.witness.iter() {
+                    write_bytes(&mut out, item);
+                }
+            }
+        }
+
+        out.extend_from_slice(&self.lock_time.to_le_bytes());
+
+        out
+    }
+
+    pub fn serialize_hex(&self) -> String {
+        self.serialize().to_lower_hex_string()
+    }
+
+    pub fn txid(&self) -> Txid {
+        Txid::from_raw_hash(sha256d::Hash::hash(&self.serialize_without_witness()))
+    }
+
+    pub fn wtxid(&self) -> Wtxid {
+        Wtxid::from_raw_hash(sha256d::Hash::hash(&self.serialize()))
+    }
+
+    /// Parse a consensus serialized transaction, checking nothing but the framing
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        let version = i32::from_le_bytes(reader.array()?);
+
+        let mut input_count = reader.compact_size()?;
+        let mut has_witness = false;
+
+        // An empty input list followed by a flag of 1 is the segwit marker
+        if input_count == 0 {
+            let flag = reader.array::<1>()?[0];
+            if flag != 0x01 {
+                return Err(format!("unknown segwit flag {}", flag));
+            }
+
+            has_witness = true;
+            input_count = reader.compact_size()?;
+        }
+
+        let mut input = Vec::new();
+        for _ in 0..input_count {
+            input.push(RawTxIn {
+                txid: reader.array()?,
+                vout: u32::from_le_bytes(reader.array()?),
+                script_sig: reader.bytes()?,
+                sequence: u32::from_le_bytes(reader.array()?),
+                witness: Vec::new(),
+            });
+        }
+
+        let output_count = reader.compact_size()?;
+        let mut output = Vec::new();
+        for _ in 0..output_count {
+            output.push(RawTxOut {
+                value: u64::from_le_bytes(reader.array()?),
+                script_pubkey: reader.bytes()?,
+            });
+        }
+
+        if has_witness {
+            for txin in input.iter_mut() {
+                let item_count = reader.compact_size()?;
+                for _ in 0..item_count {
+                    txin.witness.push(reader.bytes()?);
+                }
+            }
+
+            if input.iter().all(|txin| txin.witness.is_empty()) {
+                return Err("segwit serialization without any witness".to_string());
+            }
+        }
+
+        let lock_time = u32::from_le_bytes(reader.array()?);
+
+        if reader.position != bytes.len() {
+            return Err(format!("{} trailing bytes", bytes.len() - reader.position));
+        }
+
+        Ok(RawTransaction {
+            version,
+            lock_time,
+            input,
+            output,
+        })
+    }
+
+    pub fn deserialize_hex(hex: &str) -> Result<Self, String> {
+        let bytes = Vec::<u8>::from_hex(hex)
+            .map_err(|e| format!("invalid hex: {}", e))?;
+
+        Self::deserialize(&bytes)
+    }
+}
+
+impl From<&Transaction> for RawTransaction {
+    fn from(tx: &Transaction) -> Self {
+        RawTransaction {
+            version: tx.version.0,
+            lock_time: tx.lock_time.to_consensus_u32(),
+            input: tx.input.iter()
+                .map(|input| RawTxIn {
+                    txid: input.previous_output.txid.to_byte_array(),
+                    vout: input.previous_output.vout,
+                    script_sig: input.script_sig.to_bytes(),
+                    sequence: input.sequence.to_consensus_u32(),
+                    witness: input.witness.to_vec(),
+                })
+                .collect(),
+            output: tx.output.iter()
+                .map(|output| RawTxOut {
+                    value: output.value.to_sat(),
+                    script_pubkey: output.script_pubkey.to_bytes(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Cursor over a serialized transaction
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let end = self.position.checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| format!("unexpected end of data reading {} bytes at offset {}", length, self.position))?;
+
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn compact_size(&mut self) -> Result<usize, String> {
+        let (n, minimum) = match self.array::<1>()?[0] {
+            0xfd => (u16::from_le_bytes(self.array()?) as u64, 0xfd),
+            0xfe => (u32::from_le_bytes(self.array()?) as u64, 0x1_0000),
+            0xff => (u64::from_le_bytes(self.array()?), 0x1_0000_0000),
+            n => (n as u64, 0),
+        };
+
+        if n < minimum {
+            return Err(format!("non-canonical CompactSize {}", n));
+        }
+
+        // Anything bigger couldn't fit in the remaining bytes anyway
+        if n > self.bytes.len() as u64 {
+            return Err(format!("CompactSize {} exceeds the data length", n));
+        }
+
+        Ok(n as usize)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let length = self.compact_size()?;
+
+        Ok(self.take(length)?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::consensus::encode;
+
+    /// Version 2, one input without a scriptSig, one output
+    const LEGACY: &str = "0200000001aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0000000000ffffffff01e803000000000000015100000000";
+
+    /// `LEGACY` with a witness of two items
+    const SEGWIT: &str = "02000000000101aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0000000000ffffffff01e80300000000000001510201010301020300000000";
+
+    /// `LEGACY` in the segwit format, but with an empty witness
+    const EMPTY_WITNESS: &str = "02000000000101aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0000000000ffffffff01e80300000000000001510000000000";
+
+    fn round_trip(hex_tx: &str) {
+        let tx: Transaction = encode::deserialize_hex(hex_tx).expect("test transaction");
+        let raw = RawTransaction::from(&tx);
+
+        assert_eq!(raw.serialize_hex(), encode::serialize_hex(&tx));
+        assert_eq!(raw.txid(), tx.compute_txid());
+        assert_eq!(raw.wtxid(), tx.compute_wtxid());
+        assert_eq!(RawTransaction::deserialize_hex(hex_tx), Ok(raw));
+    }
+
+    #[test]
+    fn round_trip_legacy() {
+        round_trip(LEGACY);
+    }
+
+    #[test]
+    fn round_trip_segwit() {
+        round_trip(SEGWIT);
+    }
+
+    #[test]
+    fn compact_size_widths() {
+        let cases: [(usize, &[u8]); 4] = [
+            (252, &[0xfc]),
+            (253, &[0xfd, 0xfd, 0x00]),
+            (65_535, &[0xfd, 0xff, 0xff]),
+            (65_536, &[0xfe, 0x00, 0x00, 0x01, 0x00]),
+        ];
+
+        for (n, encoded) in cases {
+            let mut out = Vec::new();
+            write_compact_size(&mut out, n);
+            assert_eq!(out, encoded, "encoding {}", n);
+
+            // Padded so the size fits in the data
+            out.resize(out.len() + n, 0);
+            let mut reader = Reader { bytes: &out, position: 0 };
+            assert_eq!(reader.compact_size(), Ok(n), "decoding {}", n);
+        }
+    }
+
+    #[test]
+    fn rejects_non_canonical_compact_size() {
+        let cases: [&[u8]; 3] = [
+            &[0xfd, 0xfc, 0x00],
+            &[0xfe, 0xff, 0xff, 0x00, 0x00],
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00],
+        ];
+
+        for encoded in cases {
+            let mut reader = Reader { bytes: encoded, position: 0 };
+            assert!(reader.compact_size().is_err(), "accepted {:x?}", encoded);
+        }
+
+        // The input count of `LEGACY`, written in three bytes
+        let non_canonical = LEGACY.replacen("0200000001", "02000000fd0100", 1);
+        assert_eq!(RawTransaction::deserialize_hex(&non_canonical), Err("non-canonical CompactSize 1".to_string()));
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let trailing = format!("{}00", LEGACY);
+
+        assert_eq!(RawTransaction::deserialize_hex(&trailing), Err("1 trailing bytes".to_string()));
+    }
+
+    #[test]
+    fn rejects_segwit_flag_without_witnesses() {
+        assert_eq!(
+            RawTransaction::deserialize_hex(EMPTY_WITNESS),
+            Err("segwit serialization without any witness".to_string()),
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::raw::RawTransaction;
+
+use std::ops::Range;
+
+/// Ranges of `len` items to try removing, halves first, down to single items
+fn chunks(len: usize) -> Vec<Range<usize>> {
+    let mut chunks = Vec::new();
+
+    let mut size = len.div_ceil(2);
+    while size > 0 {
+        chunks.extend((0..len).step_by(size).map(|start| start..(start + size).min(len)));
+
+        size /= 2;
+    }
+
+    chunks.dedup();
+
+    chunks
+}
+
+/// Simpler versions of `bytes`: empty, either half, all zeroes
+fn smaller_bytes(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut smaller = Vec::new();
+
+    if !bytes.is_empty() {
+        smaller.push(Vec::new());
+    }
+
+    if bytes.len() > 1 {
+        let half = bytes.len() / 2;
+        smaller.push(bytes[..half].to_vec());
+        smaller.push(bytes[half..].to_vec());
+    }
+
+    if bytes.iter().any(|byte| *byte != 0) {
+        smaller.push(vec![0; bytes.len()]);
+    }
+
+    smaller
+}
+
+/// Every one step simplification of `tx` spent at `index`, roughly biggest first
+///
+/// Each candidate is strictly smaller, has fewer non-zero bytes, or spends a lower index, so
+/// shrinking always terminates.
+fn candidates(tx: &RawTransaction, index: u32) -> Vec<(RawTransaction, u32)> {
+    let mut candidates = Vec::new();
+
+    // An empty input list can't be serialized unambiguously, keep at least one
+    for chunk in chunks(tx.input.len()).into_iter().filter(|chunk| chunk.len() < tx.input.len()) {
+        let mut candidate = tx.clone();
+        candidate.input.drain(chunk.clone());
+
+        let index = match index as usize {
+            i if i >= chunk.end => index - chunk.len() as u32,
+            i if i >= chunk.start => chunk.start as u32,
+            _ => index,
+        };
+
+        candidates.push((candidate, index));
+    }
+
+    for chunk in chunks(tx.output.len()) {
+        let mut candidate = tx.clone();
+        candidate.output.drain(chunk);
+
+        candidates.push((candidate, index));
+    }
+
+    for (i, input) in tx.input.iter().enumerate() {
+        for chunk in chunks(input.witness.len()) {
+            let mut candidate = tx.clone();
+            candidate.input[i].witness.drain(chunk);
+
+            candidates.push((candidate, index));
+        }
+
+        for (j, item) in input.witness.iter().enumerate() {
+            for smaller in smaller_bytes(item) {
+                let mut candidate = tx.clone();
+                candidate.input[i].witness[j] = smaller;
+
+                candidates.push((candidate, index));
+            }
+        }
+
+        for smaller in smaller_bytes(&input.script_sig) {
+            let mut candidate = tx.clone();
+            candidate.input[i].script_sig = smaller;
+
+            candidates.push((candidate, index));
+        }
+    }
+
+    for (i, output) in tx.output.iter().enumerate() {
+        for smaller in smaller_bytes(&output.script_pubkey) {
+            let mut candidate = tx.clone();
+            candidate.output[i].script_pubkey = smaller;
+
+            candidates.push((candidate, index));
+        }
+    }
+
+    let mut smaller_indices = vec![0, index / 2, index.saturating_sub(1), tx.input.len() as u32];
+    smaller_indices.retain(|smaller| *smaller < index);
+    smaller_indices.dedup();
+
+    for smaller in smaller_indices {
+        candidates.push((tx.clone(), smaller));
+    }
+
+    let mut zeroed = Vec::new();
+
+    if tx.version != 0 {
+        zeroed.push(RawTransaction { version: 0, ..tx.clone() });
+    }
+
+    if tx.lock_time != 0 {
+        zeroed.push(RawTransaction { lock_time: 0, ..tx.clone() });
+    }
+
+    for (i, input) in tx.input.iter().enumerate() {
+        if input.txid != [0; 32] || input.vout != 0 {
+            let mut candidate = tx.clone();
+            candidate.input[i].txid = [0; 32];
+            candidate.input[i].vout = 0;
+            zeroed.push(candidate);
+        }
+
+        if input.sequence != 0 {
+            let mut candidate = tx.clone();
+            candidate.input[i].sequence = 0;
+            zeroed.push(candidate);
+        }
+    }
+
+    for (i, output) in tx.output.iter().enumerate() {
+        if output.value != 0 {
+            let mut candidate = tx.clone();
+            candidate.output[i].value = 0;
+            zeroed.push(candidate);
+        }
+    }
+
+    candidates.extend(zeroed.into_iter().map(|candidate| (candidate, index)));
+
+    candidates
+}
+
+/// Greedily simplify `tx` spent at `index` for as long as `still_fails` holds
+///
+/// Removes inputs, outputs and witness items, truncates scripts and witness items, lowers the
+/// spend index and zeroes fields, until no single simplification keeps the failure.
+pub fn shrink<F>(mut tx: RawTransaction, mut index: u32, mut still_fails: F) -> (RawTransaction, u32)
+where
+    F: FnMut(&RawTransaction, u32) -> bool,
+{
+    'shrinking: loop {
+        for (candidate, candidate_index) in candidates(&tx, index) {
+            if still_fails(&candidate, candidate_index) {
+                tx = candidate;
+                index = candidate_index;
+
+                continue 'shrinking;
+            }
+        }
+
+        return (tx, index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::raw::{
+        RawTxIn,
+        RawTxOut,
+    };
+
+    fn input(script_sig: Vec<u8>, witness: Vec<Vec<u8>>) -> RawTxIn {
+        RawTxIn {
+            txid: [0xaa; 32],
+            vout: 3,
+            script_sig,
+            sequence: 0xffff_fffe,
+            witness,
+        }
+    }
+
+    fn output(value: u64, script_pubkey: Vec<u8>) -> RawTxOut {
+        RawTxOut {
+            value,
+            script_pubkey,
+        }
+    }
+
+    fn tx() -> RawTransaction {
+        RawTransaction {
+            version: 2,
+            lock_time: 500_000,
+            input: (0..5).map(|i| input(vec![i; 20], vec![vec![i; 30], vec![i; 3]])).collect(),
+            output: (0..4).map(|i| output(1_000 * i as u64, vec![0x51; 25])).collect(),
+        }
+    }
+
+    #[test]
+    fn chunks_halve_down_to_single_items() {
+        assert_eq!(chunks(0), Vec::<Range<usize>>::new());
+        assert_eq!(chunks(1), vec![0..1]);
+        assert_eq!(chunks(5), vec![0..3, 3..5, 0..1, 1..2, 2..3, 3..4, 4..5]);
+    }
+
+    #[test]
+    fn candidates_keep_an_input_and_a_valid_index() {
+        let tx = tx();
+
+        for (candidate, index) in candidates(&tx, 3) {
+            assert!(!candidate.input.is_empty());
+            assert!(index <= 3);
+            assert_ne!((&candidate, index), (&tx, 3));
+        }
+    }
+
+    #[test]
+    fn candidates_follow_the_spent_input() {
+        let tx = tx();
+
+        // Removing an input before the spent one moves it down one
+        assert!(candidates(&tx, 3).into_iter().any(|(candidate, index)| {
+            candidate.input.len() == 4 && index == 2 && candidate.input[2] == tx.input[3]
+        }));
+    }
+
+    #[test]
+    fn always_failing_shrinks_to_the_minimum() {
+        let (tx, index) = shrink(tx(), 3, |_, _| true);
+
+        assert_eq!(tx, RawTransaction {
+            version: 0,
+            lock_time: 0,
+            input: vec![RawTxIn {
+                txid: [0; 32],
+                vout: 0,
+                script_sig: Vec::new(),
+                sequence: 0,
+                witness: Vec::new(),
+            }],
+            output: Vec::new(),
+        });
+        assert_eq!(index, 0);
+    }
+
+    #[test]
+    fn never_failing_leaves_the_transaction_alone() {
+        let (tx, index) = shrink(tx(), 3, |_, _| false);
+
+        assert_eq!(tx, self::tx());
+        assert_eq!(index, 3);
+    }
+
+    #[test]
+    fn shrinking_keeps_what_the_failure_needs() {
+        // Fails whenever some output pays exactly 3,000 with a non-empty script, at any index past 0
+        let still_fails = |tx: &RawTransaction, index: u32| {
+            index > 0 && tx.output.iter().any(|output| output.value == 3_000 && !output.script_pubkey.is_empty())
+        };
+
+        let (tx, index) = shrink(tx(), 4, still_fails);
+
+        assert!(still_fails(&tx, index));
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(index, 1);
+        assert_eq!(tx.output, vec![output(3_000, vec![0])]);
+    }
+}
@@ -0,0 +1,33 @@
+use bitcoin::Amount;
+
+use crate::raw::RawTransaction;
+
+/// Outputs whose value is above `MAX_MONEY`, as (index, value)
+pub fn amount_violations(tx: &RawTransaction) -> Vec<(usize, u64)> {
+    let max_money = Amount::MAX_MONEY.to_sat();
+
+    tx.output.iter()
+        .enumerate()
+        .filter(|(_, output)| output.value > max_money)
+        .map(|(index, output)| (index, output.value))
+        .collect()
+}
+
+/// Tag of upstream vectors whose amounts were changed, their hashes no longer match upstream's
+pub const CLAMPED_AMOUNTS: &str = "clamped-amounts";
+
+/// Clamp every output value to `MAX_MONEY`, returning the indices of the outputs changed
+///
+/// Only individual values are clamped, sums above `MAX_MONEY` are left alone since they parse
+/// fine and are covered by `--amount-edges` anyway.
+pub fn clamp_amounts(tx: &mut RawTransaction) -> Vec<usize> {
+    let max_money = Amount::MAX_MONEY.to_sat();
+
+    amount_violations(tx).into_iter()
+        .map(|(index, _)| {
+            tx.output[index].value = max_money;
+
+            index
+        })
+        .collect()
+}
@@ -0,0 +1,529 @@
+use bitcoin::{
+    consensus::encode::deserialize_hex,
+    consensus::encode::serialize_hex,
+    hashes::{
+        sha256,
+        Hash,
+    },
+    hex::DisplayHex,
+    Transaction,
+    Txid,
+    Wtxid,
+};
+
+use clap::ValueEnum;
+
+use crate::coverage::coverage_tx;
+use crate::ctv;
+use crate::edge_cases::AMOUNT_ABOVE_MAX_MONEY;
+use crate::generator::{
+    random_raw_tx,
+    random_tx,
+    GenerationParams,
+    Profile,
+};
+use crate::metamorphic::{
+    MetamorphicGroup,
+    Mutant,
+};
+use crate::oracle::Oracle;
+use crate::raw::RawTransaction;
+use crate::seed::Seed;
+use crate::upstream;
+
+use rand::RngCore;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Desc {
+    #[serde(rename = "Inputs")]
+    pub inputs: u32,
+
+    #[serde(rename = "Outputs")]
+    pub outputs: u32,
+
+    #[serde(rename = "Witness")]
+    pub witness: bool,
+
+    #[serde(rename = "Version")]
+    pub version: i32,
+
+    #[serde(rename = "scriptSigs")]
+    pub script_sigs: bool,
+}
+
+impl Desc {
+    /// Summarize the features of `tx` exercised by a test vector
+    pub fn from_tx(tx: &RawTransaction) -> Self {
+        Desc {
+            inputs: tx.input.len() as u32,
+            outputs: tx.output.len() as u32,
+            witness: tx.has_witness(),
+            version: tx.version,
+            script_sigs: tx.input.iter().any(|input| !input.script_sig.is_empty()),
+        }
+    }
+}
+
+/// Intermediate values of the template hash, all hashes hex encoded in preimage byte order
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Components {
+    /// `null` when every scriptSig is empty, and so not committed to
+    pub script_sigs_hash: Option<String>,
+
+    pub input_count: u32,
+
+    pub sequences_hash: String,
+
+    pub output_count: u32,
+
+    pub outputs_hash: String,
+
+    /// The full preimage for each of the vector's `spend_index`
+    pub preimage: Vec<String>,
+}
+
+impl Components {
+    /// Compute the components of `tx` with the native implementation
+    pub fn new(tx: &RawTransaction, spend_index: &[u32]) -> Self {
+        let components = ctv::TemplateComponents::of(tx);
+        let hex = |hash: sha256::Hash| hash.to_byte_array().to_lower_hex_string();
+
+        Components {
+            script_sigs_hash: components.script_sigs_hash.map(hex),
+            input_count: components.input_count,
+            sequences_hash: hex(components.sequences_hash),
+            output_count: components.output_count,
+            outputs_hash: hex(components.outputs_hash),
+            preimage: spend_index.iter()
+                .map(|index| ctv::preimage(tx, *index).to_lower_hex_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CtvTestVector {
+    #[serde(rename = "hex_tx")]
+    pub transaction: String,
+
+    pub spend_index: Vec<u32>,
+
+    pub result: Vec<String>,
+
+    /// Byte order of `result`, display (what `getdefaulttemplate` returns) when absent, as in
+    /// upstream files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_byte_order: Option<ctv::ByteOrder>,
+
+    /// `result` in display byte order, as `getdefaulttemplate` returns it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub result_display: Vec<String>,
+
+    /// `result` in internal byte order, as pushed in a CTV script
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub result_internal: Vec<String>,
+
+    /// Not needed to check a vector, so tolerate files that describe it differently
+    #[serde(default)]
+    pub desc: Desc,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub txid: Option<Txid>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wtxid: Option<Wtxid>,
+
+    /// Seed this vector was generated from, see `regen`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<Seed>,
+
+    /// What a hand-built vector exercises
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Intermediate values, to tell which part of a disagreeing implementation is wrong
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub components: Option<Box<Components>>,
+}
+
+/// The transaction of a single test vector, entirely determined by `rng`
+fn vector_tx<R: RngCore>(rng: &mut R, params: &GenerationParams, coverage: bool) -> Transaction {
+    if coverage {
+        coverage_tx(rng, params)
+    } else {
+        random_tx(rng, params)
+    }
+}
+
+/// The spend indices of a random vector: the first two inputs, then two arbitrary indices
+fn random_spend_indices<R: RngCore>(rng: &mut R) -> Vec<u32> {
+    let mut spend_index: Vec<u32> = vec![0, 1];
+    spend_index.extend((0..2).map(|_| rng.next_u32()));
+
+    spend_index
+}
+
+/// The transaction and spend indices of the vector `CtvTestVector::generate` builds from `seed`
+pub fn seeded_tx(seed: Seed, params: &GenerationParams, coverage: bool) -> (Transaction, Vec<u32>) {
+    let mut rng = seed.rng();
+
+    let tx = vector_tx(&mut rng, params, coverage);
+    let spend_index = random_spend_indices(&mut rng);
+
+    (tx, spend_index)
+}
+
+impl CtvTestVector {
+    /// Build the test vector of `tx` spent at each of `spend_index`, without any rust-bitcoin checks
+    ///
+    /// Every hash the oracle returns is cross-checked against the native implementation, panicking
+    /// on failure or disagreement.
+    pub fn from_raw_tx(tx: &RawTransaction, spend_index: Vec<u32>, oracle: &dyn Oracle) -> Self {
+        let mut result: Vec<String> = Vec::new();
+
+        let hextx = tx.serialize_hex();
+
+        let desc = Desc::from_tx(tx);
+
+        for i in spend_index.iter() {
+            let template = oracle.template_hash(tx, *i)
+                .unwrap_or_else(|e| panic!("{} failed on input {} of {}: {}", oracle.name(), i, hextx, e));
+
+            let native_template = ctv::template_hash_hex(&ctv::default_template_hash(tx, *i));
+
+            assert!(template.eq_ignore_ascii_case(&native_template),
+                "native template hash {} disagrees with {} for input {} of {}", native_template, oracle.name(), i, hextx);
+
+            result.push(template);
+        }
+
+        let result_internal = result.iter()
+            .map(|hash| ctv::reverse_hex(hash).expect("oracle returns hex"))
+            .collect();
+
+        CtvTestVector {
+            transaction: hextx,
+            spend_index,
+            result_byte_order: Some(ctv::ByteOrder::Display),
+            result_display: result.clone(),
+            result_internal,
+            result,
+            desc,
+            txid: Some(tx.txid()),
+            wtxid: Some(tx.wtxid()),
+            seed: None,
+            tags: Vec::new(),
+            components: None,
+        }
+    }
+
+    /// Add the intermediate values of the hash, panicking unless each preimage hashes to the
+    /// oracle's `result`
+    pub fn with_components(self) -> Self {
+        let tx = RawTransaction::deserialize_hex(&self.transaction)
+            .expect("deserialize hex");
+
+        let components = Components::new(&tx, &self.spend_index);
+
+        for (index, result) in self.spend_index.iter().zip(self.result.iter()) {
+            let hash = ctv::template_hash_hex(&sha256::Hash::hash(&ctv::preimage(&tx, *index)));
+
+            assert!(hash.eq_ignore_ascii_case(result),
+                "preimage hashes to {} but the oracle's result is {} for input {} of {}", hash, result, index, self.transaction);
+        }
+
+        CtvTestVector {
+            components: Some(Box::new(components)),
+            ..self
+        }
+    }
+
+    /// Build the test vector of `tx` spent at each of `spend_index`
+    ///
+    /// Panics if rust-bitcoin can't round-trip `tx`, or serializes it differently than
+    /// `RawTransaction` does.
+    pub fn from_tx(tx: &Transaction, spend_index: Vec<u32>, oracle: &dyn Oracle) -> Self {
+        let hextx = serialize_hex(tx);
+
+        let _deserialized_hex: Transaction = deserialize_hex(&hextx)
+            .expect("deserialize hex");
+
+        let vector = Self::from_raw_tx(&RawTransaction::from(tx), spend_index, oracle);
+
+        assert_eq!(vector.transaction, hextx, "raw serialization disagrees with rust-bitcoin");
+
+        vector
+    }
+
+    /// Generate a single random test vector, entirely determined by `seed`
+    ///
+    /// `coverage` selects the steered transactions of a coverage run.
+    pub fn generate(seed: Seed, params: &GenerationParams, coverage: bool, oracle: &dyn Oracle) -> Self {
+        let (tx, spend_index) = seeded_tx(seed, params, coverage);
+
+        CtvTestVector {
+            seed: Some(seed),
+            ..Self::from_tx(&tx, spend_index, oracle)
+        }
+    }
+
+    /// Generate a single raw amount test vector, entirely determined by `seed`
+    pub fn generate_raw(seed: Seed, params: &GenerationParams, oracle: &dyn Oracle) -> Self {
+        let mut rng = seed.rng();
+
+        let tx = random_raw_tx(&mut rng, params);
+        let spend_index = random_spend_indices(&mut rng);
+
+        let tags = if upstream::amount_violations(&tx).is_empty() {
+            Vec::new()
+        } else {
+            vec![AMOUNT_ABOVE_MAX_MONEY.to_string()]
+        };
+
+        CtvTestVector {
+            seed: Some(seed),
+            tags,
+            ..Self::from_raw_tx(&tx, spend_index, oracle)
+        }
+    }
+}
+
+/// Version of the layout of the files this tool writes, bumped on incompatible changes
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Header describing how the file it's found in was made, enough to reproduce each vector in it
+///
+/// The options choosing how many vectors and which extra sets were generated aren't recorded, so
+/// the file as a whole can't be rebuilt from it.
+///
+/// Every field but `seed` is optional so headers written by older versions still parse.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Metadata {
+    /// Version of this tool that wrote the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator_version: Option<String>,
+
+    /// `FORMAT_VERSION` of the file, 0 if written before it was recorded
+    #[serde(default)]
+    pub format_version: u32,
+
+    pub seed: Seed,
+
+    #[serde(default)]
+    pub profile: Profile,
+
+    /// The ranges actually used, after `--config` and command line overrides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<GenerationParams>,
+
+    /// Minimum vectors per coverage bucket, if generated in coverage mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<usize>,
+
+    /// The oracle that produced the `result` hashes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oracle: Option<String>,
+
+    /// Version of the oracle's implementation, the node's user agent for `rpc`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oracle_version: Option<String>,
+
+    /// When the file was generated, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl Metadata {
+    /// Header of a file generated now by `oracle` from `seed`
+    pub fn new(seed: Seed, profile: Profile, params: &GenerationParams, oracle: &dyn Oracle) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock after 1970")
+            .as_secs();
+
+        Metadata {
+            generator_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            format_version: FORMAT_VERSION,
+            seed,
+            profile,
+            params: Some(params.clone()),
+            coverage: None,
+            oracle: Some(oracle.name()),
+            oracle_version: oracle.version(),
+            timestamp: Some(timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CtvTestVectorEntry {
+    TestVector(CtvTestVector),
+    Metamorphic(Box<MetamorphicGroup>),
+    Metadata(Metadata),
+    Documentation(String),
+}
+
+impl CtvTestVectorEntry {
+    /// Add intermediate values to every vector in the entry, see `CtvTestVector::with_components`
+    pub fn with_components(self) -> Self {
+        match self {
+            CtvTestVectorEntry::TestVector(vector) => CtvTestVectorEntry::TestVector(vector.with_components()),
+            CtvTestVectorEntry::Metamorphic(group) => CtvTestVectorEntry::Metamorphic(Box::new(MetamorphicGroup {
+                base: group.base.with_components(),
+                mutations: group.mutations.into_iter()
+                    .map(|mutant| Mutant {
+                        mutated: mutant.mutated.with_components(),
+                        ..mutant
+                    })
+                    .collect(),
+            })),
+            entry @ (CtvTestVectorEntry::Metadata(_) | CtvTestVectorEntry::Documentation(_)) => entry,
+        }
+    }
+}
+
+/// Documentation string upstream `ctvhash.json` starts with
+pub const UPSTREAM_DOCUMENTATION: &str =
+    "{\"hex_tx\":string (hex tx), \"spend_index\":[number], \"result\": [string (hex hash)]}";
+
+/// JSON Schema of files written with `Format::Upstream`
+pub const UPSTREAM_SCHEMA: &str = include_str!("../schemas/upstream.schema.json");
+
+/// JSON Schema of files written with `Format::Extended`
+pub const EXTENDED_SCHEMA: &str = include_str!("../schemas/extended.schema.json");
+
+/// Layout of the vector files this tool writes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Exactly the keys and `desc` layout of upstream `ctvhash.json`, for tools that consume it
+    Upstream,
+    /// Upstream's keys plus everything this tool adds: header, seeds, tags, components, byte
+    /// orders, txid and wtxid, metamorphic groups
+    #[default]
+    Extended,
+}
+
+impl Format {
+    pub fn schema(&self) -> &'static str {
+        match self {
+            Format::Upstream => UPSTREAM_SCHEMA,
+            Format::Extended => EXTENDED_SCHEMA,
+        }
+    }
+
+    /// Write a whole vector file in this format
+    ///
+    /// The upstream format starts with upstream's documentation string instead of the header, and
+    /// splits metamorphic groups into their base and mutated vectors.
+    pub fn write_entries<W: std::io::Write>(&self, out: W, entries: Vec<CtvTestVectorEntry>) -> serde_json::Result<()> {
+        match self {
+            Format::Extended => serde_json::to_writer_pretty(out, &entries),
+            Format::Upstream => {
+                let mut upstream = vec![UpstreamEntry::Documentation(UPSTREAM_DOCUMENTATION.to_string())];
+
+                for entry in entries {
+                    match entry {
+                        CtvTestVectorEntry::TestVector(vector) => {
+                            upstream.push(UpstreamEntry::TestVector(vector.into()));
+                        }
+                        CtvTestVectorEntry::Metamorphic(group) => {
+                            let MetamorphicGroup { base, mutations } = *group;
+
+                            upstream.push(UpstreamEntry::TestVector(base.into()));
+                            upstream.extend(mutations.into_iter()
+                                .map(|mutant| UpstreamEntry::TestVector(mutant.mutated.into())));
+                        }
+                        CtvTestVectorEntry::Metadata(_) | CtvTestVectorEntry::Documentation(_) => {}
+                    }
+                }
+
+                serde_json::to_writer_pretty(out, &upstream)
+            }
+        }
+    }
+
+    /// Write a single vector in this format
+    ///
+    /// The extended format writes the bare vector, the upstream format a whole file holding only it.
+    pub fn write_vector<W: std::io::Write>(&self, out: W, vector: CtvTestVector) -> serde_json::Result<()> {
+        match self {
+            Format::Extended => serde_json::to_writer_pretty(out, &vector),
+            Format::Upstream => self.write_entries(out, vec![CtvTestVectorEntry::TestVector(vector)]),
+        }
+    }
+}
+
+/// A test vector with exactly the keys of upstream `ctvhash.json`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamTestVector {
+    pub hex_tx: String,
+    pub spend_index: Vec<u32>,
+    /// Always in display byte order
+    pub result: Vec<String>,
+    pub desc: Desc,
+}
+
+impl From<CtvTestVector> for UpstreamTestVector {
+    fn from(vector: CtvTestVector) -> Self {
+        let result = match vector.result_byte_order.unwrap_or_default() {
+            ctv::ByteOrder::Display => vector.result,
+            ctv::ByteOrder::Internal => vector.result.iter()
+                .map(|hash| ctv::reverse_hex(hash).expect("hex result"))
+                .collect(),
+        };
+
+        UpstreamTestVector {
+            hex_tx: vector.transaction,
+            spend_index: vector.spend_index,
+            result,
+            desc: vector.desc,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum UpstreamEntry {
+    TestVector(UpstreamTestVector),
+    Documentation(String),
+}
+
+/// Read a whole vector file, in this tool's format or upstream's
+pub fn load_entries<R: std::io::Read>(reader: R) -> Result<Vec<CtvTestVectorEntry>, serde_json::Error> {
+    serde_json::from_reader(std::io::BufReader::new(reader))
+}
+
+/// Read only the test vectors of a vector file, skipping documentation, metadata and metamorphic
+/// groups
+pub fn load_vectors<R: std::io::Read>(reader: R) -> Result<Vec<CtvTestVector>, serde_json::Error> {
+    let vectors = load_entries(reader)?
+        .into_iter()
+        .filter_map(|entry| match entry {
+            CtvTestVectorEntry::TestVector(vector) => Some(vector),
+            CtvTestVectorEntry::Metamorphic(_)
+            | CtvTestVectorEntry::Metadata(_)
+            | CtvTestVectorEntry::Documentation(_) => None,
+        })
+        .collect();
+
+    Ok(vectors)
+}
+
+/// Read only the test vectors of the vector file at `path`
+pub fn load_vectors_file<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Vec<CtvTestVector>> {
+    let file = std::fs::File::open(path)?;
+
+    Ok(load_vectors(file)?)
+}